@@ -0,0 +1,30 @@
+#!/bin/sh
+# Times the tree walker and the bytecode VM on the loop-heavy benchmark script.
+# Given a git revision (`./bench.sh main`), that revision is built in a temporary worktree and timed the same way
+cargo build --release || exit 1
+script="bench/loop_heavy.naq"
+runs=5
+
+# Runs `$1` on the script with each backend and prints the average time
+time_binary() {
+    for mode in "" "--vm"; do
+        echo "$1 $script $mode"
+        start=$(date +%s%N)
+        for _ in $(seq $runs); do
+            "$1" $script $mode > /dev/null || exit 1
+        done
+        end=$(date +%s%N)
+        echo "  $(( (end - start) / runs / 1000000 )) ms per run (average of $runs)"
+    done
+}
+
+if [ -n "$1" ]; then
+    base=$(mktemp -d)
+    git worktree add --detach "$base" "$1" > /dev/null || exit 1
+    trap 'git worktree remove --force "$base"' EXIT
+    (cd "$base" && cargo build --release --target-dir "$base/target") || exit 1
+    echo "== $1"
+    time_binary "$base/target/release/naqua"
+    echo "== working tree"
+fi
+time_binary ./target/release/naqua
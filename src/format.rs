@@ -0,0 +1,59 @@
+use crate::tokenize::token::TokenKind;
+use crate::tokenize::tokenizer::Tokenizer;
+use crate::util::err::NaquaResult;
+
+const INDENT: &str = "    ";
+
+// Re-indents a script by its braces and keeps everything else, comments included, as written.
+// Trailing whitespace goes, and runs of blank lines are squashed into one
+pub fn format(source: &str) -> NaquaResult<String> {
+    let tokens = Tokenizer::new(source.to_string(), 0).tokenize()?;
+
+    // Per line: change in depth, and how many `}` it starts with
+    let lines = source.lines().count();
+    let mut delta = vec![0i32; lines + 2];
+    let mut leading_closes = vec![0i32; lines + 2];
+    let mut started = vec![false; lines + 2];
+    for token in &tokens {
+        let line = token.span.line;
+        match token.kind {
+            TokenKind::NewLine => continue,
+            TokenKind::OpenBrace => delta[line] += 1,
+            TokenKind::CloseBrace => {
+                delta[line] -= 1;
+                if !started[line] {
+                    leading_closes[line] += 1;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        started[line] = true;
+    }
+
+    let mut out = String::new();
+    let mut depth = 0;
+    // Starts out true so blank lines at the top are dropped
+    let mut after_blank = true;
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let text = text.trim();
+        if text.is_empty() {
+            if !after_blank {
+                out.push('\n');
+            }
+            after_blank = true;
+        } else {
+            let indent = (depth - leading_closes[line]).max(0) as usize;
+            out.push_str(&INDENT.repeat(indent));
+            out.push_str(text);
+            out.push('\n');
+            after_blank = false;
+        }
+        depth = (depth + delta[line]).max(0);
+    }
+    while out.ends_with("\n\n") {
+        out.pop();
+    }
+    Ok(out)
+}
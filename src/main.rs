@@ -1,20 +1,29 @@
-use std::fs;
-use std::path::Path;
-use crate::parse::parser::Parser;
 use crate::poll::poll;
-use crate::runtime::runtime::Runner;
-use crate::tokenize::token::Token;
-use crate::tokenize::tokenizer::Tokenizer;
+use crate::runtime::runtime::STACK_SIZE;
 
 mod util;
 mod tokenize;
 mod parse;
 mod runtime;
+mod vm;
+mod module;
+mod format;
+mod repl;
 mod poll;
 
 fn main() {
-    match poll() {
-        Ok(()) => return,
-        Err(e) => eprintln!("Error: {e}")
-    }
+    // Scripts run on a thread of their own, which gets the stack deep macro calls need
+    let status = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| match poll() {
+            Ok(status) => status,
+            Err(e) => {
+                eprintln!("Error: {e}");
+                2
+            }
+        })
+        .expect("failed to start the interpreter thread")
+        .join()
+        .unwrap_or(101);
+    std::process::exit(status);
 }
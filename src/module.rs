@@ -0,0 +1,85 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::parse::{Macro, Node, NodeKind, ParserResult};
+use crate::parse::parser::Parser;
+use crate::tokenize::tokenizer::Tokenizer;
+use crate::util::err::{NaquaError, NaquaResult, ParseError};
+
+// Loads a script and every file it includes into a single program
+pub struct Loader {
+    log: bool,
+    // Name and source of every loaded file, indexed by `Span::file`
+    pub files: Vec<(String, String)>,
+    // Files currently being loaded, outermost first
+    active: Vec<PathBuf>,
+}
+impl Loader {
+    pub fn new(log: bool) -> Self {
+        Self { log, files: Vec::new(), active: Vec::new() }
+    }
+    pub fn load(&mut self, file: &Path, source: String) -> NaquaResult<ParserResult> {
+        self.active.push(fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf()));
+        let result = self.load_inner(file, source);
+        self.active.pop();
+        result
+    }
+    fn load_inner(&mut self, file: &Path, source: String) -> NaquaResult<ParserResult> {
+        let id = self.files.len();
+        self.files.push((file.display().to_string(), source.clone()));
+
+        let tokens = Tokenizer::new(source, id).tokenize()?;
+        if self.log { println!("Tokens ({}): \n{:?}\n", file.display(), tokens); }
+        let ast = Parser::new(&tokens, self.log).parse()?;
+
+        let mut macros = ast.macros;
+        // Namespaces taken by this file's includes, with the file and path each came from
+        let mut namespaces: HashMap<String, (PathBuf, &String)> = HashMap::new();
+        for (path, span) in &ast.includes {
+            let included = file.parent().unwrap_or(Path::new("")).join(path);
+            let include_err = |reason: String| NaquaError::Parse(ParseError::IncludeFailed { path: path.clone(), reason }, *span);
+
+            let canonical = fs::canonicalize(&included).map_err(|e| include_err(e.to_string()))?;
+            if self.active.contains(&canonical) {
+                return Err(NaquaError::Parse(ParseError::IncludeCycle(path.clone()), *span));
+            }
+            let namespace = included.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            match namespaces.get(&namespace) {
+                // Including a file again adds nothing
+                Some((file, _)) if *file == canonical => continue,
+                Some((_, first)) => return Err(NaquaError::Parse(ParseError::NamespaceClash { namespace, first: first.to_string() }, *span)),
+                None => { namespaces.insert(namespace.clone(), (canonical, path)); }
+            }
+            let lib_source = fs::read_to_string(&included).map_err(|e| include_err(e.to_string()))?;
+            let lib = self.load(&included, lib_source)?;
+
+            if let Some(node) = lib.nodes.first() {
+                return Err(NaquaError::Parse(ParseError::StatementInInclude, node.span));
+            }
+            macros.extend(namespaced(&namespace, lib.macros));
+        }
+
+        Ok(ParserResult {
+            nodes: ast.nodes,
+            macros,
+            includes: ast.includes,
+        })
+    }
+}
+
+// Prefixes the macros of an included file with `namespace::`, along with the calls between them
+fn namespaced(namespace: &str, macros: Vec<(String, Macro)>) -> impl Iterator<Item = (String, Macro)> + '_ {
+    let names: HashSet<String> = macros.iter().map(|(name, _)| name.clone()).collect();
+    macros.into_iter().map(move |(name, mut mac)| {
+        for node in &mut mac.body {
+            node.visit_mut(&mut |n: &mut Node| {
+                if let NodeKind::Run(target, _) | NodeKind::Spawn(target, _) = &mut n.kind {
+                    if names.contains(target.as_str()) {
+                        *target = format!("{namespace}::{target}");
+                    }
+                }
+            });
+        }
+        (format!("{namespace}::{name}"), mac)
+    })
+}
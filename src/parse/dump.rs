@@ -0,0 +1,96 @@
+use std::fmt::Write;
+use crate::parse::{LoopKind, Macro, Node, NodeKind};
+use crate::util::types::Number;
+
+// Renders a program as an indented tree, one node per line with its position, for `naqua ast`
+pub fn dump<'m>(nodes: &[Node], macros: impl IntoIterator<Item = (&'m String, &'m Macro)>) -> String {
+    let mut out = String::new();
+    let mut macros: Vec<_> = macros.into_iter().collect();
+    macros.sort_by_key(|(name, _)| *name);
+    for (name, m) in macros {
+        line(&mut out, 0, &format!("define {name}({})", m.params.join(", ")), m.span.line, m.span.col);
+        for node in &m.body {
+            node_tree(&mut out, node, 1, &m.params);
+        }
+    }
+    for node in nodes {
+        node_tree(&mut out, node, 0, &[]);
+    }
+    out
+}
+
+fn node_tree(out: &mut String, node: &Node, depth: usize, params: &[String]) {
+    let label = match &node.kind {
+        NodeKind::Literal(Number::Int(n)) => n.to_string(),
+        NodeKind::Literal(Number::Float(n)) => n.to_string(),
+        NodeKind::Literal(Number::Char(c)) => format!("{c:?}"),
+        NodeKind::Literal(Number::Big(n)) => n.to_string(),
+        NodeKind::Literal(Number::Thought) => "thought".to_string(),
+        NodeKind::Char(_) => "char".to_string(),
+        NodeKind::Print(_) => "print".to_string(),
+        NodeKind::If(..) => "if".to_string(),
+        NodeKind::Loop { label, kind, .. } => {
+            let kind = match kind {
+                LoopKind::Forever => "loop",
+                LoopKind::While(_) => "while",
+                LoopKind::Repeat(_) => "repeat",
+                LoopKind::For(..) => "for",
+            };
+            match label {
+                Some(l) => format!("{l}: {kind}"),
+                None => kind.to_string()
+            }
+        }
+        NodeKind::Assign(..) => "in".to_string(),
+        NodeKind::Eval(_, op, _) => op.to_string(),
+        NodeKind::Negate(_) => "-".to_string(),
+        NodeKind::Not(_) => "not".to_string(),
+        NodeKind::Convert(c, _) => c.to_str().to_string(),
+        NodeKind::Think(_) => "think".to_string(),
+        NodeKind::Out(_) => "out".to_string(),
+        NodeKind::Run(name, _) => format!("run {name}"),
+        NodeKind::Spawn(name, _) => format!("spawn {name}"),
+        NodeKind::Param(i) => params.get(*i).cloned().unwrap_or_else(|| format!("param {i}")),
+        NodeKind::Return(_) => "return".to_string(),
+        NodeKind::Break(label) => label.as_ref().map_or("break".to_string(), |l| format!("break {l}")),
+        NodeKind::Continue(label) => label.as_ref().map_or("continue".to_string(), |l| format!("continue {l}")),
+        NodeKind::Arg(_) => "arg".to_string(),
+        NodeKind::ArgCount => "args".to_string(),
+        NodeKind::Exit(_) => "exit".to_string(),
+        NodeKind::Read => "read".to_string(),
+        NodeKind::ReadChar => "readchar".to_string(),
+        NodeKind::Str(text) => format!("{text:?}"),
+    };
+    line(out, depth, &label, node.span.line, node.span.col);
+
+    // Branches and loop bodies get their own heading so they can be told apart from the values before them
+    match &node.kind {
+        NodeKind::If(cond, body, otherwise) => {
+            node_tree(out, cond, depth + 1, params);
+            block(out, "then", body, depth + 1, params);
+            if !otherwise.is_empty() {
+                block(out, "else", otherwise, depth + 1, params);
+            }
+        }
+        NodeKind::Loop { kind, body, .. } => {
+            match kind {
+                LoopKind::Forever => {}
+                LoopKind::While(n) | LoopKind::Repeat(n) => node_tree(out, n, depth + 1, params),
+                LoopKind::For(cell, first, last) => {
+                    [cell, first, last].into_iter().for_each(|n| node_tree(out, n, depth + 1, params));
+                }
+            }
+            block(out, "do", body, depth + 1, params);
+        }
+        _ => node.children().into_iter().for_each(|n| node_tree(out, n, depth + 1, params)),
+    }
+}
+
+fn block(out: &mut String, heading: &str, body: &[Node], depth: usize, params: &[String]) {
+    let _ = writeln!(out, "{}{heading}", "  ".repeat(depth));
+    body.iter().for_each(|n| node_tree(out, n, depth + 1, params));
+}
+
+fn line(out: &mut String, depth: usize, label: &str, line: usize, col: usize) {
+    let _ = writeln!(out, "{}{label:<24} {line}:{col}", "  ".repeat(depth));
+}
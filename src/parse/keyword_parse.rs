@@ -1,102 +1,167 @@
-use crate::parse::Node;
+use crate::parse::{Node, NodeKind};
 use crate::parse::parser::Parser;
-use crate::tokenize::token::Token;
-use crate::util::types::Keyword;
+use crate::tokenize::token::TokenKind;
+use crate::util::err::{NaquaError, NaquaResult, ParseError};
+use crate::util::span::Span;
+use crate::util::types::Number;
 
 impl<'t> Parser<'t> {
-    pub fn parse_think(&mut self) -> Result<Option<Node>, String> {
+    pub fn parse_think(&mut self) -> NaquaResult<Option<Node>> {
+        if self.log { println!("Parsing Think..."); }
+        let start = self.span();
         self.advance();
-        let expr = self.expr.clone();
 
-        let result = match self.peek() {
-            Some(Token::NewLine) => {
+        let value = self.parse_num("after 'think'")?;
+        let span = start.to(value.span);
+        Ok(Some(Node::new(NodeKind::Think(Box::new(value)), span)))
+    }
+    pub fn parse_print(&mut self) -> NaquaResult<Option<Node>> {
+        if self.log { println!("Parsing Print..."); }
+        let start = self.span();
+        self.advance();
+
+        let value = match self.text() {
+            Some(text) => text,
+            None => self.parse_num("after 'print'")?
+        };
+        let span = start.to(value.span);
+        Ok(Some(Node::new(NodeKind::Print(Box::new(value)), span)))
+    }
+    // A string literal at the current token, for the places that take text instead of a value
+    pub fn text(&mut self) -> Option<Node> {
+        match self.peek() {
+            Some(TokenKind::Str(text)) => {
+                let node = Node::new(NodeKind::Str(text.clone()), self.span());
                 self.advance();
-                Ok(None)
+                Some(node)
             }
-            Some(Token::Data(n)) => {
-                match self.parse_num(0, Token::Data(n.clone()))? {
-                    Some(r) => Ok(Some(Node::Think(Box::new(r)))),
-                    None => Ok(None)
-                }
-            }
-            Some(Token::Keyword(Keyword::Out)) => {
-                if self.log { println!("Parsing Think Out..."); }
-                match self.parse_num(0, Token::Keyword(Keyword::Out))? {
-                    Some(m) => Ok(Some(Node::Think(Box::new(m)))),
-                    None => Err(self.err("Unable to retrieve from a non-existent stack index!"))
-                }
-            }
-            Some(_) => Err(self.err("Only data types can be imagined!")),
-            None => Err(self.err("Unable to imagine nothing!"))
-        };
-        self.advance();
-        result
+            _ => None
+        }
     }
-    pub fn parse_print(&mut self) -> Result<Option<Node>, String> {
-        if self.log { println!("Parsing Print..."); }
+    pub fn parse_run(&mut self) -> NaquaResult<Option<Node>> {
+        if self.log { println!("Parsing Run..."); }
+        let start = self.span();
+
+        let call = self.parse_call("run")?;
+        Ok(call.map(|(name, args)| Node::new(NodeKind::Run(name, args), self.span_from(start))))
+    }
+    pub fn parse_spawn(&mut self) -> NaquaResult<Option<Node>> {
+        if self.log { println!("Parsing Spawn..."); }
+        let start = self.span();
+
+        let call = self.parse_call("spawn")?;
+        Ok(call.map(|(name, args)| Node::new(NodeKind::Spawn(name, args), self.span_from(start))))
+    }
+    // `<keyword> <name>` with an optional `(arg, ...)` list; a bare keyword at the end of a line does nothing
+    fn parse_call(&mut self, after: &'static str) -> NaquaResult<Option<(String, Vec<Node>)>> {
         self.advance();
 
-        let result = match self.peek() {
-            Some(Token::NewLine) => {
+        let name = match self.peek() {
+            Some(TokenKind::NewLine) => {
                 self.advance();
-                Ok(None)
+                return Ok(None);
             }
-            Some(Token::Data(n)) => {
-                if self.log { println!("Parsing Print Data..."); }
-                match self.parse_num(0, Token::Data(n.clone()))? {
-                    Some(r) => Ok(Some(Node::Print(Box::new(r)))),
-                    None => Ok(None)
-                }
+            Some(TokenKind::RTKeyword(s)) => s.clone(),
+            found => return Err(self.err(ParseError::ExpectedMacroName { after, found: found.cloned() }))
+        };
+        self.advance();
+
+        let mut args = Vec::new();
+        if self.peek() == Some(&TokenKind::OpenParen) {
+            let open = self.span();
+            self.advance();
+            if self.peek() == Some(&TokenKind::CloseParen) {
+                self.advance();
+                return Ok(Some((name, args)));
             }
-            Some(Token::Keyword(Keyword::Out)) => {
-                if self.log { println!("Parsing Print Out..."); }
-                match self.parse_num(0, Token::Keyword(Keyword::Out))? {
-                    Some(m) => Ok(Some(Node::Print(Box::new(m)))),
-                    None => Err(self.err("Unable to retrieve from a non-existent stack index!"))
+            loop {
+                args.push(self.parse_num("as a macro argument")?);
+                match self.peek() {
+                    Some(TokenKind::Comma) => { self.advance(); }
+                    Some(TokenKind::CloseParen) => {
+                        self.advance();
+                        break;
+                    }
+                    _ => return Err(NaquaError::Parse(ParseError::UnclosedParen, open))
                 }
             }
-            Some(_) => Err(self.err("Only data types can be printed!")),
-            None => Err(self.err("Unable to print nothing!"))
+        }
+        Ok(Some((name, args)))
+    }
+    pub fn parse_return(&mut self) -> NaquaResult<Option<Node>> {
+        if self.log { println!("Parsing Return..."); }
+        let start = self.span();
+        if self.params.is_none() {
+            return Err(self.err(ParseError::ReturnOutsideMacro));
+        }
+        self.advance();
+
+        // A bare `return` ends the macro without a value
+        let value = match self.peek() {
+            Some(TokenKind::NewLine | TokenKind::CloseBrace) | None => None,
+            _ => Some(Box::new(self.parse_num("after 'return'")?))
         };
+        Ok(Some(Node::new(NodeKind::Return(value), self.span_from(start))))
+    }
+    pub fn parse_exit(&mut self) -> NaquaResult<Option<Node>> {
+        if self.log { println!("Parsing Exit..."); }
+        let start = self.span();
         self.advance();
-        result
+
+        // A bare `exit` ends the program successfully
+        let status = match self.peek() {
+            Some(TokenKind::NewLine | TokenKind::CloseBrace) | None => Node::new(NodeKind::Literal(Number::Int(0)), start),
+            _ => self.parse_num("after 'exit'")?
+        };
+        Ok(Some(Node::new(NodeKind::Exit(Box::new(status)), self.span_from(start))))
     }
-    pub fn parse_run(&mut self) -> Result<Option<Node>, String> {
-        if self.log { println!("Parsing Run..."); }
+    pub fn parse_break(&mut self) -> NaquaResult<Option<Node>> {
+        if self.log { println!("Parsing Break..."); }
+        let start = self.span();
+        if self.loops.is_empty() {
+            return Err(self.err(ParseError::BreakOutsideLoop));
+        }
         self.advance();
 
-        let result = match self.peek() {
-            Some(Token::NewLine) => {
-                self.advance();
-                Ok(None)
-            }
-            Some(Token::RTKeyword(s)) => {
-                Ok(Some(Node::Run(s.clone())))
-            }
-            Some(Token::Keyword(_)) => Err(self.err("Unable to run a macro with reserved name!")),
-            Some(Token::Data(_)) => Err(self.err("Unable to run a data type!")),
-            _ => Err(self.err("Unable to run a non-existent macro!"))
+        let label = self.parse_loop_label()?;
+        Ok(Some(Node::new(NodeKind::Break(label), self.span_from(start))))
+    }
+    pub fn parse_continue(&mut self) -> NaquaResult<Option<Node>> {
+        if self.log { println!("Parsing Continue..."); }
+        let start = self.span();
+        if self.loops.is_empty() {
+            return Err(self.err(ParseError::ContinueOutsideLoop));
+        }
+        self.advance();
+
+        let label = self.parse_loop_label()?;
+        Ok(Some(Node::new(NodeKind::Continue(label), self.span_from(start))))
+    }
+    // Optional label after `break`/`continue`, which must name an enclosing loop
+    fn parse_loop_label(&mut self) -> NaquaResult<Option<String>> {
+        let label = match self.peek() {
+            Some(TokenKind::RTKeyword(s)) => s.clone(),
+            _ => return Ok(None)
         };
+        if !self.loops.iter().any(|l| l.as_deref() == Some(label.as_str())) {
+            return Err(NaquaError::Parse(ParseError::UnknownLabel(label), self.span()));
+        }
         self.advance();
-        result
+        Ok(Some(label))
     }
-    pub fn parse_spawn(&mut self) -> Result<Option<Node>, String> {
-        if self.log { println!("Parsing Run..."); }
+    // `include "<path>"`; the file itself is loaded once parsing is done
+    pub fn parse_include(&mut self) -> NaquaResult<(String, Span)> {
+        if self.log { println!("Parsing Include..."); }
+        let start = self.span();
         self.advance();
 
-        let result = match self.peek() {
-            Some(Token::NewLine) => {
+        match self.peek() {
+            Some(TokenKind::Str(path)) => {
+                let path = path.clone();
                 self.advance();
-                Ok(None)
-            }
-            Some(Token::RTKeyword(s)) => {
-                Ok(Some(Node::Spawn(s.clone())))
+                Ok((path, self.span_from(start)))
             }
-            Some(Token::Keyword(_)) => Err(self.err("Unable to run a macro with reserved name!")),
-            Some(Token::Data(_)) => Err(self.err("Unable to run a data type!")),
-            _ => Err(self.err("Unable to run a non-existent macro!"))
-        };
-        self.advance();
-        result
+            found => Err(self.err(ParseError::ExpectedPath(found.cloned())))
+        }
     }
-}
\ No newline at end of file
+}
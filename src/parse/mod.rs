@@ -1,42 +1,145 @@
-use std::collections::HashMap;
-use crate::util::types::{Number, Operator};
+use crate::util::span::Span;
+use crate::util::types::{Conversion, Number, Operator};
 
 pub mod parser;
+pub mod resolve;
+pub mod optimize;
+pub mod dump;
 mod keyword_parse;
 mod num_parse;
 mod rel_parse;
 
 #[derive(Debug, Clone)]
-pub enum Node {
+pub enum NodeKind {
     Literal(Number),
     Char(Box<Node>),
     Print(Box<Node>),
-    If(Box<Node>, Box<Vec<Node>>),
-    Loop(Box<Vec<Node>>),
+    If(Box<Node>, Vec<Node>, Vec<Node>),
+    Loop { label: Option<String>, kind: LoopKind, body: Vec<Node> },
     Assign(Box<Node>, Box<Node>),
     Eval(Box<Node>, Operator, Box<Node>),
-    Define(String, Box<Vec<Node>>),
+    Negate(Box<Node>),
+    Not(Box<Node>),
+    Convert(Conversion, Box<Node>),
     Think(Box<Node>),
     Out(Box<Node>),
-    Run(String),
-    Spawn(String),
-    Break
+    // Macro name and argument values
+    Run(String, Vec<Node>),
+    Spawn(String, Vec<Node>),
+    // Position of a parameter in the frame of the running macro
+    Param(usize),
+    // Optional value handed back to the caller
+    Return(Option<Box<Node>>),
+    // Optional label of the loop to break out of / continue
+    Break(Option<String>),
+    Continue(Option<String>),
+    // Script argument at an index, and the number of script arguments
+    Arg(Box<Node>),
+    ArgCount,
+    // Ends the whole program with a status code
+    Exit(Box<Node>),
+    // Next number / next character code point from stdin
+    Read,
+    ReadChar,
+    // Text, which only appears directly under `print` and as the value of `<index> in`
+    Str(String)
+}
+
+#[derive(Debug, Clone)]
+pub enum LoopKind {
+    Forever,
+    While(Box<Node>),
+    Repeat(Box<Node>),
+    // Cell, first value and last value (inclusive) of the counter
+    For(Box<Node>, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub span: Span,
 }
 impl Node {
-    pub fn to_num(&self) -> Result<Number, &'static str> {
-        match self {
-            Node::Literal(n) => Ok(*n),
-            _ => Err("Unable to convert a non-number node to a number!")
+    pub fn new(kind: NodeKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+    // Calls `f` on this node, then on every node nested inside it
+    pub fn visit(&self, f: &mut impl FnMut(&Node)) {
+        f(self);
+        for child in self.children() {
+            child.visit(f);
+        }
+    }
+    // Nodes directly nested inside this one, in evaluation order
+    pub fn children(&self) -> Vec<&Node> {
+        match &self.kind {
+            NodeKind::Char(n) | NodeKind::Print(n) | NodeKind::Negate(n) | NodeKind::Not(n) | NodeKind::Convert(_, n)
+            | NodeKind::Think(n) | NodeKind::Out(n) | NodeKind::Arg(n) | NodeKind::Exit(n) | NodeKind::Return(Some(n)) => vec![n],
+            NodeKind::Assign(a, b) | NodeKind::Eval(a, _, b) => vec![a, b],
+            NodeKind::If(cond, body, otherwise) => std::iter::once(cond.as_ref()).chain(body).chain(otherwise).collect(),
+            NodeKind::Loop { kind, body, .. } => {
+                let head: Vec<&Node> = match kind {
+                    LoopKind::Forever => vec![],
+                    LoopKind::While(n) | LoopKind::Repeat(n) => vec![n],
+                    LoopKind::For(cell, first, last) => vec![cell, first, last],
+                };
+                head.into_iter().chain(body).collect()
+            }
+            NodeKind::Run(_, args) | NodeKind::Spawn(_, args) => args.iter().collect(),
+            NodeKind::Literal(_) | NodeKind::Param(_) | NodeKind::Break(_) | NodeKind::Continue(_) | NodeKind::Return(None)
+            | NodeKind::ArgCount | NodeKind::Read | NodeKind::ReadChar | NodeKind::Str(_) => vec![],
+        }
+    }
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut Node)) {
+        f(self);
+        match &mut self.kind {
+            NodeKind::Char(n) | NodeKind::Print(n) | NodeKind::Negate(n) | NodeKind::Not(n) | NodeKind::Convert(_, n)
+            | NodeKind::Think(n) | NodeKind::Out(n) | NodeKind::Arg(n) | NodeKind::Exit(n) | NodeKind::Return(Some(n)) => n.visit_mut(f),
+            NodeKind::Assign(a, b) | NodeKind::Eval(a, _, b) => {
+                a.visit_mut(f);
+                b.visit_mut(f);
+            }
+            NodeKind::If(cond, body, otherwise) => {
+                cond.visit_mut(f);
+                body.iter_mut().chain(otherwise).for_each(|n| n.visit_mut(f));
+            }
+            NodeKind::Loop { kind, body, .. } => {
+                match kind {
+                    LoopKind::Forever => {}
+                    LoopKind::While(n) | LoopKind::Repeat(n) => n.visit_mut(f),
+                    LoopKind::For(cell, first, last) => {
+                        cell.visit_mut(f);
+                        first.visit_mut(f);
+                        last.visit_mut(f);
+                    }
+                }
+                body.iter_mut().for_each(|n| n.visit_mut(f));
+            }
+            NodeKind::Run(_, args) | NodeKind::Spawn(_, args) => args.iter_mut().for_each(|n| n.visit_mut(f)),
+            NodeKind::Literal(_) | NodeKind::Param(_) | NodeKind::Break(_) | NodeKind::Continue(_) | NodeKind::Return(None)
+            | NodeKind::ArgCount | NodeKind::Read | NodeKind::ReadChar | NodeKind::Str(_) => {}
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Macro {
+    pub params: Vec<String>,
+    pub body: Vec<Node>,
+    // Location of the macro's name in its definition
+    pub span: Span,
+}
+
 #[derive(Debug, Clone)]
 pub enum ExprType {
     Node(Option<Node>),
-    Macro((String, Box<Vec<Node>>)),
+    Macro((String, Macro)),
+    // Path as written, and the span of the `include` statement
+    Include((String, Span)),
 }
 pub struct ParserResult {
     pub(crate) nodes: Vec<Node>,
-    pub(crate) macros: Option<HashMap<String, Box<Vec<Node>>>>
-}
\ No newline at end of file
+    // Definitions in source order; duplicates are caught by `resolve`
+    pub(crate) macros: Vec<(String, Macro)>,
+    pub(crate) includes: Vec<(String, Span)>,
+}
@@ -1,175 +1,185 @@
-use std::panic::resume_unwind;
-use crate::parse::Node;
+use crate::parse::{Node, NodeKind};
 use crate::parse::parser::Parser;
-use crate::tokenize::token::Token;
-use crate::util::err::err_code;
-use crate::util::types::{Keyword, Number};
-use crate::util::types::Number::Thought;
+use crate::tokenize::token::TokenKind;
+use crate::util::err::{NaquaError, NaquaResult, ParseError, TokenizeError};
+use crate::util::types::{Conversion, Keyword, Number, Operator};
 
 impl<'t> Parser<'t> {
-    pub fn parse_num(&mut self, depth: u8, token: Token) -> Result<Option<Node>, String> {
-        if self.log { println!("Parsing number {token:?}..."); }
-        let mut node = match token {
-            Token::Data(num) => {
-                let r = if num.is_thought() { Node::Literal(Thought) } else { Node::Literal(num) };
+    // Parses a full value expression.
+    // `context` describes where the value is expected, for error messages.
+    pub fn parse_num(&mut self, context: &'static str) -> NaquaResult<Node> {
+        if self.log { println!("Parsing number {context}..."); }
+        self.parse_binary(0, context)
+    }
+
+    // Precedence climbing: only operators binding at least as tightly as `min_prec` are consumed here.
+    // A postfix `char` binds loosest of all, so it converts everything before it:
+    // `10 + 55 char` is `(10 + 55) char`, and `65 char + 1` is `(65 char) + 1`
+    fn parse_binary(&mut self, min_prec: u8, context: &'static str) -> NaquaResult<Node> {
+        let mut lhs = self.parse_operand(context)?;
+
+        loop {
+            match self.peek() {
+                Some(&TokenKind::OpToken(op)) if op.precedence() >= min_prec => {
+                    if self.log { println!("Operator '{op}' found!"); }
+                    self.advance(); // Move past the operator
+
+                    let prec = op.precedence();
+                    let next_min = if op.is_right_assoc() { prec } else { prec + 1 };
+                    let rhs = self.parse_binary(next_min, "after an operator")?;
+                    let span = lhs.span.to(rhs.span);
+                    lhs = Node::new(NodeKind::Eval(Box::new(lhs), op, Box::new(rhs)), span);
+                }
+                Some(TokenKind::Keyword(Keyword::Char)) if min_prec == 0 => {
+                    if self.log { println!("Char found!"); }
+                    let span = lhs.span.to(self.span());
+                    self.advance();
+                    lhs = Node::new(NodeKind::Char(Box::new(lhs)), span);
+                }
+                _ => break
+            }
+        }
+        Ok(lhs)
+    }
+
+    // A single value: a number, `thought`, `out x`, `arg x`, `args`, `read`, `readchar`, a conversion like `floor x`,
+    // a parameter, a macro call, a parenthesized expression or a negation of one
+    fn parse_operand(&mut self, context: &'static str) -> NaquaResult<Node> {
+        let start = self.span();
+        match self.peek() {
+            // A '-' where a value is expected is unary; it binds looser than `^`, so `-2 ^ 2` is `-(2 ^ 2)`
+            Some(TokenKind::OpToken(Operator::Sub)) => {
                 self.advance();
-                r
-            },
-            Token::Keyword(Keyword::Out) => {
-                let r = self.parse_out()?.ok_or_else(|| format!("Unable to parse an out token! Found at expression #{}", self.expr))?;
+                // `2 ^ 63` is only a valid literal when negated directly, and not raised to a power first
+                if matches!(self.peek(), Some(TokenKind::Data(Number::Big(_))))
+                    && !matches!(self.peek_next(), Some(TokenKind::OpToken(Operator::Exp))) {
+                    let span = start.to(self.span());
+                    self.advance();
+                    return Ok(Node::new(NodeKind::Literal(Number::Int(i64::MIN)), span));
+                }
+                let operand = self.parse_binary(Operator::Exp.precedence(), "after '-'")?;
+                let span = start.to(operand.span);
+                Ok(match operand.kind {
+                    NodeKind::Literal(Number::Int(n)) => Node::new(NodeKind::Literal(Number::Int(-n)), span),
+                    NodeKind::Literal(Number::Float(n)) => Node::new(NodeKind::Literal(Number::Float(-n)), span),
+                    _ => Node::new(NodeKind::Negate(Box::new(operand)), span)
+                })
+            }
+            // `not` binds looser than comparisons, so `not a == b` is `not (a == b)`
+            Some(TokenKind::Keyword(Keyword::Not)) => {
                 self.advance();
-                r
+                let operand = self.parse_binary(Operator::Eq.precedence(), "after 'not'")?;
+                let span = start.to(operand.span);
+                Ok(Node::new(NodeKind::Not(Box::new(operand)), span))
             }
-            _ => return Err(format!("Unable to parse a non-number token! Found at expression #{}", self.expr))
-        };
-
-        match self.peek().cloned() {
-            Some(Token::NewLine) => {
-                if self.log { println!("Newline found, continuing!"); }
-                self.undo();
-                return Ok(Some(node));
+            Some(TokenKind::Data(Number::Big(n))) => {
+                Err(NaquaError::Tokenize(TokenizeError::NumberOutOfRange(n.to_string()), start))
             }
-            Some(Token::OpToken(o)) => {
-                self.advance(); // Move past the operator
-                let next = match self.peek().cloned() {
-                    Some(Token::Data(n)) => {
-                        self.parse_num(depth + 1, Token::Data(n))?.ok_or_else(|| format!("Unable to parse a number after an operator! Found at expression #{}", self.expr))?
-                    }
-                    Some(Token::Keyword(Keyword::Out)) => {
-                        self.parse_num(depth + 1, Token::Keyword(Keyword::Out))?.ok_or_else(|| format!("Unable to parse a number after an operator! Found at expression #{}", self.expr))?
-                    }
-                    _ => return Err(format!("Unable to find a number after an operator! Found at expression #{}", self.expr))
+            Some(TokenKind::Data(n)) => {
+                let n = n.clone();
+                self.advance();
+                Ok(Node::new(NodeKind::Literal(n), start))
+            }
+            Some(TokenKind::Keyword(Keyword::Out)) => self.parse_out(),
+            Some(TokenKind::Keyword(Keyword::Arg)) => {
+                self.advance();
+                let index = self.parse_operand("after 'arg'")?;
+                let span = start.to(index.span);
+                Ok(Node::new(NodeKind::Arg(Box::new(index)), span))
+            }
+            // Conversions bind like `out`, so `floor 7 / 2` is `(floor 7) / 2`
+            Some(&TokenKind::Keyword(k)) if Conversion::from_keyword(k).is_some() => {
+                let conversion = Conversion::from_keyword(k).unwrap();
+                self.advance();
+                let context = match conversion {
+                    Conversion::Int => "after 'int'",
+                    Conversion::Float => "after 'float'",
+                    Conversion::Floor => "after 'floor'",
+                    Conversion::Ceil => "after 'ceil'",
+                    Conversion::Round => "after 'round'",
+                    Conversion::Abs => "after 'abs'",
                 };
-                node = Node::Eval(
-                    Box::new(node),
-                    o.clone(),
-                    Box::new(next),
-                );
+                let operand = self.parse_operand(context)?;
+                let span = start.to(operand.span);
+                Ok(Node::new(NodeKind::Convert(conversion, Box::new(operand)), span))
             }
-            _ => {
-                self.undo();
-                if self.log { println!("No operator found!") }
-            },
-        }
-
-        if self.log { println!("Checking for char..."); }
-        
-        let result = match self.advance() {
-            Some(Token::NewLine) => {
-                Ok(Some(node))
+            Some(TokenKind::Keyword(Keyword::Args)) => {
+                self.advance();
+                Ok(Node::new(NodeKind::ArgCount, start))
             }
-            Some(&Token::Keyword(Keyword::Char)) => {
-                if depth == 0 {
-                    if self.log { println!("Char found!"); }
-                    Ok(Some(Node::Char(Box::new(node))))
-                } else {
-                    if self.log { println!("Char found, but it's not the root expression's!"); }
-                    self.undo();
-                    Ok(Some(node))
+            Some(TokenKind::Keyword(Keyword::Read)) => {
+                self.advance();
+                Ok(Node::new(NodeKind::Read, start))
+            }
+            Some(TokenKind::Keyword(Keyword::ReadChar)) => {
+                self.advance();
+                Ok(Node::new(NodeKind::ReadChar, start))
+            }
+            Some(TokenKind::Keyword(Keyword::Run)) => match self.parse_run()? {
+                Some(call) => Ok(call),
+                None => Err(NaquaError::Parse(ParseError::ExpectedMacroName { after: "run", found: Some(TokenKind::NewLine) }, start))
+            },
+            Some(TokenKind::RTKeyword(name)) => {
+                let index = self.params.as_ref().and_then(|p| p.iter().position(|param| param == name));
+                match index {
+                    Some(i) => {
+                        self.advance();
+                        Ok(Node::new(NodeKind::Param(i), start))
+                    }
+                    None => Err(self.err(ParseError::UnknownName(name.clone())))
                 }
             }
-            Some(_) | None => {
-                if self.log { println!("No char found!"); }
-                self.undo();
-                Ok(Some(node))
+            Some(TokenKind::OpenParen) => {
+                self.advance();
+                let inner = self.parse_binary(0, "inside parentheses")?;
+                match self.peek() {
+                    Some(TokenKind::CloseParen) => {
+                        self.advance();
+                        Ok(Node { span: self.span_from(start), ..inner })
+                    }
+                    _ => Err(NaquaError::Parse(ParseError::UnclosedParen, start))
+                }
             }
-        };
-
-        if self.log { println!("Done parsing number {token:?}"); }
-        result
+            _ => Err(self.expected_value(context))
+        }
     }
 
-    pub fn parse_num_head(&mut self) -> Result<Option<Node>, String> {
-        let num = match self.peek().unwrap().clone() {
-            Token::Data(n) => n,
-            _ => return Err(err_code(102))
-        };
-        if self.log { println!("Parsing number head {num:?}..."); }
+    // Statements that start with a value: `<index> in <value>` or `<index> in "<text>"`
+    pub fn parse_num_head(&mut self) -> NaquaResult<Option<Node>> {
+        if self.log { println!("Parsing number head..."); }
+        let index = self.parse_binary(0, "as a stack index")?;
 
-        if num.is_float() {
-            return Err(format!("Floats are not valid indices! Found at expression #{}", self.expr));
+        if let NodeKind::Literal(Number::Float(n)) = index.kind {
+            return Err(NaquaError::Parse(ParseError::FloatIndex(n), index.span));
         }
 
-        if self.log { println!("Parsing number within head {num:?}..."); }
-        let result = self.parse_num(0, Token::Data(num))?.unwrap();
-        let expr = self.expr.clone();
-
         if self.log { println!("Head Peek: {:?}", self.peek()); }
-        let next = match self.advance() {
-            Some(t) => t.clone(),
-            None => return Err(format!("Unable to process a lone number '{:?}'! Found at expression #{expr}", self.peek()))
-        };
-        if self.log { println!("NEXT: {next:?}"); }
-
-        let result = match next {
-            Token::NewLine => {
-                self.advance();
-                Ok(None)
-            }
-            Token::Keyword(Keyword::In) => {
+        match self.peek() {
+            Some(TokenKind::Keyword(Keyword::In)) => {
                 if self.log { println!("In found!"); }
                 self.advance(); // Move past the 'in' keyword
-                match self.peek().clone() {
-                    Some(n) => match self.parse_num(0, n.clone()) {
-                        Ok(Some(t)) => Ok(Some(Node::Assign(Box::new(result), Box::new(t)))),
-                        Ok(None) => Err(format!("Unable to assign stack index to nothing! Found at expression #{expr}")),
-                        Err(e) => Err(e)
-                    },
-                    None => Err(format!("Unable to assign stack index to nothing! Found at expression #{expr}"))
-                }
-            }
-            Token::Keyword(Keyword::Out) => {
-                match self.parse_out() {
-                    Ok(Some(m)) => Ok(Some(Node::Out(Box::new(m)))),
-                    Ok(None) => Err(format!("Unable to retrieve from a non-existent stack index! Found at expression #{expr}")),
-                    Err(e) => Err(e)
-                }
-            }
-            Token::Data(_) => {
-                self.advance(); // Move past the number
-                Err(format!("Token '{next:?}' is not allowed to follow a heading number! Found at expression #{expr}"))
+                let value = match self.text() {
+                    Some(text) => text,
+                    None => self.parse_num("after 'in'")?
+                };
+                let span = index.span.to(value.span);
+                Ok(Some(Node::new(NodeKind::Assign(Box::new(index), Box::new(value)), span)))
             }
-            _ => Err(format!("Token '{next:?}' is not allowed to follow a heading number! Found at expression #{expr}"))
-        };
-
-        self.advance();
-        if self.log { println!("Done parsing number head {num:?}"); }
-        result
+            Some(TokenKind::NewLine) | None => Err(NaquaError::Parse(ParseError::LoneValue, index.span)),
+            Some(next) => Err(self.err(ParseError::UnexpectedToken(next.clone())))
+        }
     }
 
-    pub fn parse_out(&mut self) -> Result<Option<Node>, String> {
+    // `out` binds tighter than any operator, so `out 1 + out 2` adds two cells
+    pub fn parse_out(&mut self) -> NaquaResult<Node> {
         if self.log { println!("Parsing Out..."); }
+        let start = self.span();
         self.advance();
 
-        let result = match self.peek().cloned() {
-            Some(Token::NewLine) => {
-                self.advance();
-                Ok(None)
-            }
-            Some(Token::Data(n)) => {
-                match n {
-                    Number::Int(_) => {
-                        let r = Node::Out(Box::new(Node::Literal(n)));
-                        Ok(Some(r))
-                    }
-                    Number::Float(_) => {
-                        let r = Node::Out(Box::new(Node::Literal(n)));
-                        Ok(Some(r))
-                    }
-                    Number::Thought => {
-                        let r = Node::Out(Box::new(Node::Literal(Number::Thought)));
-                        Ok(Some(r))
-                    }
-                }
-            }
-            Some(_) => {
-                Err(format!("Unable to get a non-integer index! Found at expression #{}", self.expr))
-            }
-            None => Err(format!("Unable to retrieve data from a non-existent index! Found at expression #{}", self.expr))
-        };
+        let index = self.parse_operand("after 'out'")?;
+        let span = start.to(index.span);
 
         if self.log { println!("Done parsing Out!"); }
-
-        result
+        Ok(Node::new(NodeKind::Out(Box::new(index)), span))
     }
-}
\ No newline at end of file
+}
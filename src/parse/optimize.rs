@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use crate::parse::{LoopKind, Macro, Node, NodeKind};
+use crate::parse::resolve::Program;
+use crate::runtime::op_eval::{arith, convert, Arith};
+use crate::util::types::{Number, Operator};
+
+// Macros whose body has at most this many nodes are inlined at their call sites
+const INLINE_LIMIT: usize = 12;
+
+// Rewrites a resolved program into a cheaper one with the same output:
+// literal expressions are folded, `if`s with a literal condition are replaced by the branch they always take,
+// small macros called with literal arguments are inlined, and statements after `break`, `continue`, `return` or `exit` are dropped
+pub fn optimize(program: &mut Program) {
+    let plain = Optimizer { inlinable: HashMap::new() };
+    for m in program.macros.values_mut() {
+        m.body = plain.block(std::mem::take(&mut m.body));
+    }
+
+    let inlinable = program.macros.iter()
+        .filter(|(_, m)| inline_kind(m).is_some())
+        .map(|(name, m)| (name.clone(), m.clone()))
+        .collect();
+    let optimizer = Optimizer { inlinable };
+    for m in program.macros.values_mut() {
+        m.body = optimizer.block(std::mem::take(&mut m.body));
+    }
+    program.nodes = optimizer.block(std::mem::take(&mut program.nodes));
+}
+
+#[derive(PartialEq)]
+enum Inline {
+    // Runs for its effects: no `return`, so it can replace a `run` statement
+    Statement,
+    // A single `return <value>`, so it can replace a `run` used as a value
+    Value,
+}
+fn inline_kind(m: &Macro) -> Option<Inline> {
+    let mut size = 0;
+    let mut calls = false;
+    let mut returns = false;
+    for node in &m.body {
+        node.visit(&mut |n| {
+            size += 1;
+            match n.kind {
+                NodeKind::Run(..) | NodeKind::Spawn(..) => calls = true,
+                NodeKind::Return(_) => returns = true,
+                _ => {}
+            }
+        });
+    }
+    // Calls are left alone so recursion can't blow up the inlined code
+    if calls || size > INLINE_LIMIT {
+        return None;
+    }
+    match m.body.as_slice() {
+        _ if !returns => Some(Inline::Statement),
+        [Node { kind: NodeKind::Return(Some(_)), .. }] => Some(Inline::Value),
+        _ => None
+    }
+}
+
+struct Optimizer {
+    inlinable: HashMap<String, Macro>,
+}
+impl Optimizer {
+    fn block(&self, body: Vec<Node>) -> Vec<Node> {
+        let mut out = Vec::with_capacity(body.len());
+        for node in body {
+            self.statement(node, &mut out);
+            // Nothing after an unconditional jump can run
+            if let Some(NodeKind::Break(_) | NodeKind::Continue(_) | NodeKind::Return(_) | NodeKind::Exit(_)) = out.last().map(|n| &n.kind) {
+                break;
+            }
+        }
+        out
+    }
+
+    fn statement(&self, node: Node, out: &mut Vec<Node>) {
+        let span = node.span;
+        let kind = match node.kind {
+            NodeKind::If(cond, body, otherwise) => {
+                let cond = self.expr(*cond);
+                let (body, otherwise) = (self.block(body), self.block(otherwise));
+                match constant(&cond) {
+                    Some(n) => {
+                        out.extend(if n.eval_type().unwrap().is_truthy() { body } else { otherwise });
+                        return;
+                    }
+                    None => NodeKind::If(Box::new(cond), body, otherwise)
+                }
+            }
+            NodeKind::Loop { label, kind, body } => {
+                let kind = match kind {
+                    LoopKind::Forever => LoopKind::Forever,
+                    LoopKind::While(cond) => LoopKind::While(self.boxed(cond)),
+                    LoopKind::Repeat(count) => LoopKind::Repeat(self.boxed(count)),
+                    LoopKind::For(cell, first, last) => LoopKind::For(self.boxed(cell), self.boxed(first), self.boxed(last)),
+                };
+                NodeKind::Loop { label, kind, body: self.block(body) }
+            }
+            NodeKind::Run(name, args) => {
+                let args: Vec<Node> = args.into_iter().map(|a| self.expr(a)).collect();
+                if let Some(body) = self.inline(&name, &args, Inline::Statement) {
+                    out.extend(self.block(body));
+                    return;
+                }
+                NodeKind::Run(name, args)
+            }
+            NodeKind::Spawn(name, args) => NodeKind::Spawn(name, args.into_iter().map(|a| self.expr(a)).collect()),
+            NodeKind::Print(v) => NodeKind::Print(self.boxed(v)),
+            NodeKind::Think(v) => NodeKind::Think(self.boxed(v)),
+            NodeKind::Assign(i, v) => NodeKind::Assign(self.boxed(i), self.boxed(v)),
+            NodeKind::Return(v) => NodeKind::Return(v.map(|v| self.boxed(v))),
+            NodeKind::Exit(v) => NodeKind::Exit(self.boxed(v)),
+            kind => kind
+        };
+        out.push(Node::new(kind, span));
+    }
+
+    // Optimizes a boxed expression, reusing its allocation
+    fn boxed(&self, mut node: Box<Node>) -> Box<Node> {
+        let placeholder = Node::new(NodeKind::Literal(Number::Int(0)), node.span);
+        *node = self.expr(std::mem::replace(&mut *node, placeholder));
+        node
+    }
+    fn expr(&self, node: Node) -> Node {
+        let span = node.span;
+        let kind = match node.kind {
+            NodeKind::Eval(l, op, r) => {
+                let (l, r) = (self.expr(*l), self.expr(*r));
+                match (constant(&l), constant(&r)) {
+                    (Some(a), Some(b)) => match fold(a, op, b) {
+                        Some(n) => NodeKind::Literal(n),
+                        None => NodeKind::Eval(Box::new(l), op, Box::new(r))
+                    },
+                    _ => NodeKind::Eval(Box::new(l), op, Box::new(r))
+                }
+            }
+            NodeKind::Not(v) => {
+                let v = self.expr(*v);
+                match constant(&v) {
+                    Some(n) => NodeKind::Literal(Number::Int((!n.eval_type().unwrap().is_truthy()) as i64)),
+                    None => NodeKind::Not(Box::new(v))
+                }
+            }
+            NodeKind::Negate(v) => {
+                let v = self.expr(*v);
+                match constant(&v) {
+                    Some(Number::Int(n)) if n != i64::MIN => NodeKind::Literal(Number::Int(-n)),
+                    Some(Number::Float(n)) => NodeKind::Literal(Number::Float(-n)),
+                    _ => NodeKind::Negate(Box::new(v))
+                }
+            }
+            NodeKind::Convert(c, v) => {
+                let v = self.expr(*v);
+                match constant(&v).and_then(|n| convert(n.eval_type()?, c, Arith::Checked).ok()).map(|v| v.to_num()) {
+                    Some(n) => NodeKind::Literal(n),
+                    None => NodeKind::Convert(c, Box::new(v))
+                }
+            }
+            NodeKind::Char(v) => NodeKind::Char(self.boxed(v)),
+            NodeKind::Out(v) => NodeKind::Out(self.boxed(v)),
+            NodeKind::Run(name, args) => {
+                let args: Vec<Node> = args.into_iter().map(|a| self.expr(a)).collect();
+                if let Some(mut body) = self.inline(&name, &args, Inline::Value) {
+                    if let Some(Node { kind: NodeKind::Return(Some(value)), .. }) = body.pop() {
+                        return self.expr(*value);
+                    }
+                }
+                NodeKind::Run(name, args)
+            }
+            kind => kind
+        };
+        Node::new(kind, span)
+    }
+
+    // Body of `name` with its parameters replaced by `args`, if the call can be inlined.
+    // Only literal arguments are substituted, so nothing is evaluated more or less often than before
+    fn inline(&self, name: &str, args: &[Node], kind: Inline) -> Option<Vec<Node>> {
+        let m = self.inlinable.get(name)?;
+        if inline_kind(m)? != kind || m.params.len() != args.len() || !args.iter().all(|a| constant(a).is_some()) {
+            return None;
+        }
+        let mut body = m.body.clone();
+        for node in &mut body {
+            node.visit_mut(&mut |n| {
+                if let NodeKind::Param(i) = n.kind {
+                    n.kind = args[i].kind.clone();
+                }
+            });
+        }
+        Some(body)
+    }
+}
+
+// The value of a number literal; `thought` changes at runtime, so it isn't one
+fn constant(node: &Node) -> Option<Number> {
+    match &node.kind {
+        NodeKind::Literal(n @ (Number::Int(_) | Number::Float(_) | Number::Char(_))) => Some(n.clone()),
+        _ => None
+    }
+}
+
+// Evaluates `l op r` ahead of time. Anything that fails or overflows is left for the runtime,
+// which only reports it if the code actually runs, and handles overflow the way `--arith` asks
+fn fold(l: Number, op: Operator, r: Number) -> Option<Number> {
+    use Operator::*;
+    if let And | Or = op {
+        let (a, b) = (l.eval_type()?.is_truthy(), r.eval_type()?.is_truthy());
+        return Some(Number::Int(if op == And { a && b } else { a || b } as i64));
+    }
+    arith(l, op, r, Arith::Checked).ok().map(|v| v.to_num())
+}
@@ -1,24 +1,28 @@
-use std::collections::HashMap;
-use std::fmt::format;
-use std::thread::sleep;
 use colored::Colorize;
-use crate::parse::{ExprType, Node, ParserResult};
-use crate::tokenize::token::Token;
-use crate::util::types::{Keyword, Number};
+use crate::parse::{ExprType, ParserResult};
+use crate::tokenize::token::{Token, TokenKind};
+use crate::util::err::{NaquaResult, ParseError};
+use crate::util::span::Span;
+use crate::util::types::{Keyword, Number, Operator};
 
 pub struct Parser<'t> {
-    tokens: &'t Vec<Token>,
+    tokens: &'t [Token],
     pub pos: usize,
     pub expr: usize,
     pub log: bool,
+    // Labels of the loops enclosing the current position, innermost last
+    pub loops: Vec<Option<String>>,
+    // Parameter names of the macro being defined, if any
+    pub params: Option<Vec<String>>,
 }
 impl<'t> Parser<'t> {
-    pub fn new(tokens: &'t Vec<Token>, log: bool) -> Self {
-        Self { tokens, pos: 0, expr: 0, log }
+    pub fn new(tokens: &'t [Token], log: bool) -> Self {
+        Self { tokens, pos: 0, expr: 0, log, loops: Vec::new(), params: None }
     }
-    pub fn parse(&mut self) -> Result<ParserResult, String> {
+    pub fn parse(&mut self) -> NaquaResult<ParserResult> {
         let mut nodes = Vec::new();
         let mut macros = Vec::new();
+        let mut includes = Vec::new();
         //println!("Parsing...");
         loop {
             match self.parse_expression() {
@@ -27,6 +31,7 @@ impl<'t> Parser<'t> {
                     match n {
                         ExprType::Node(Some(node)) => nodes.push(node),
                         ExprType::Macro(node) => macros.push(node),
+                        ExprType::Include(include) => includes.push(include),
                         _ => {}
                     }
                     self.expr += 1;
@@ -36,74 +41,84 @@ impl<'t> Parser<'t> {
             }
         }
         //println!("Parsed!");
-        let mut macro_map = HashMap::new();
-        for (name, body) in macros {
-            macro_map.insert(name, body);
-        };
-        
         Ok(ParserResult {
             nodes,
-            macros: if !macro_map.is_empty() {
-                Some(macro_map)
-            } else {
-                None
-            }
+            macros,
+            includes
         })
     }
-    pub fn parse_expression(&mut self) -> Result<Option<ExprType>, String> {
-        let expr = self.expr.clone();
+    pub fn parse_expression(&mut self) -> NaquaResult<Option<ExprType>> {
+        let expr = self.expr;
         self.skip_newlines();
 
-        let token = match self.peek().clone() {
+        let token = match self.peek() {
             Some(token) => token.clone(),
             None => return Ok(None)
         };
 
         if self.log {
-            eprintln!("{}", format!("Parsing expression #{expr}, starting with token '{token:?}'...").bright_blue());
+            eprintln!("{}", format!("Parsing expression #{expr} at {}, starting with token '{token:?}'...", self.span()).bright_blue());
         }
         let r = match token {
-            Token::Keyword(k) => {
+            TokenKind::Keyword(k) => {
                 match k {
                     Keyword::Think => ExprType::Node(self.parse_think()?),
-                    Keyword::In => return Err(self.err("'in' must follow a stack index!")),
-                    Keyword::Out => ExprType::Node(self.parse_out()?),
+                    Keyword::In => return Err(self.err(ParseError::MisplacedIn)),
+                    Keyword::Out | Keyword::Arg | Keyword::Args | Keyword::Read | Keyword::ReadChar => ExprType::Node(self.parse_num_head()?),
                     Keyword::Print => ExprType::Node(self.parse_print()?),
                     Keyword::If => ExprType::Node(self.parse_if()?),
-                    Keyword::Loop => ExprType::Node(self.parse_loop()?),
+                    Keyword::Loop => ExprType::Node(self.parse_loop(None)?),
+                    Keyword::While => ExprType::Node(self.parse_while(None)?),
+                    Keyword::Repeat => ExprType::Node(self.parse_repeat(None)?),
+                    Keyword::For => ExprType::Node(self.parse_for(None)?),
                     Keyword::Run => ExprType::Node(self.parse_run()?),
                     Keyword::Define => {
                         ExprType::Macro(self.parse_def()?)
                     },
-                    Keyword::Break => {
-                        self.advance();
-                        ExprType::Node(Some(Node::Break))
-                    },
+                    Keyword::Break => ExprType::Node(self.parse_break()?),
+                    Keyword::Continue => ExprType::Node(self.parse_continue()?),
+                    Keyword::Return => ExprType::Node(self.parse_return()?),
+                    Keyword::Exit => ExprType::Node(self.parse_exit()?),
+                    Keyword::Include => ExprType::Include(self.parse_include()?),
                     Keyword::Spawn => ExprType::Node(self.parse_spawn()?),
                     _ => {
-                        return Err(self.err("Keyword not recognized!"));
+                        return Err(self.err(ParseError::UnexpectedToken(token)));
                     }
                 }
             }
-            Token::Data(_) => ExprType::Node(self.parse_num_head()?),
-            Token::OpToken(o) => return Err(self.err(format!("Unexpected operator '{}' found!", o.to_char()).as_str())),
-            _ => return Ok(None)
+            TokenKind::RTKeyword(_) if self.peek_next() == Some(&TokenKind::Colon) => ExprType::Node(self.parse_labeled()?),
+            TokenKind::Data(_) | TokenKind::OpenParen | TokenKind::RTKeyword(_) => ExprType::Node(self.parse_num_head()?),
+            TokenKind::OpToken(Operator::Sub) => ExprType::Node(self.parse_num_head()?),
+            TokenKind::OpToken(_) => return Err(self.err(ParseError::UnexpectedToken(token))),
+            _ => return Err(self.err(ParseError::UnexpectedToken(token)))
         };
 
         Ok(Some(r))
     }
-    fn skip_newlines(&mut self) {
-        while let Some(Token::NewLine) = self.peek() {
+    pub fn expect_keyword(&mut self, keyword: Keyword) -> NaquaResult<()> {
+        match self.peek() {
+            Some(TokenKind::Keyword(k)) if *k == keyword => {
+                self.advance();
+                Ok(())
+            }
+            found => Err(self.err(ParseError::ExpectedKeyword { keyword: keyword.to_str(), found: found.cloned() }))
+        }
+    }
+    pub fn skip_newlines(&mut self) {
+        while let Some(TokenKind::NewLine) = self.peek() {
             self.advance();
         }
     }
-    pub fn peek(&self) -> Option<&Token> {
+    pub fn peek(&self) -> Option<&TokenKind> {
         if self.log {
             self.display();
         }
-        self.tokens.get(self.pos)
+        self.tokens.get(self.pos).map(|t| &t.kind)
     }
-    pub fn advance(&mut self) -> Option<&Token> {
+    pub fn peek_next(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos + 1).map(|t| &t.kind)
+    }
+    pub fn advance(&mut self) -> Option<&TokenKind> {
         self.pos += 1;
         let p = self.peek();
         if self.log {
@@ -111,27 +126,38 @@ impl<'t> Parser<'t> {
         }
         p
     }
-    pub fn undo(&mut self) -> Option<&Token> {
-        self.pos -= 1;
-        if self.log {
-            println!("Undoing...");
-            self.display();
+    // Span of the current token, or an empty span just past the last token at the end of input
+    pub fn span(&self) -> Span {
+        match self.tokens.get(self.pos) {
+            Some(t) => t.span,
+            None => match self.tokens.last() {
+                // Tokens never span lines; the width is in bytes, which only differs from chars inside literals
+                Some(t) => Span { start: t.span.end, col: t.span.col + (t.span.end - t.span.start), ..t.span },
+                None => Span::new(0, 0, 1, 1)
+            }
+        }
+    }
+    // Span from `start` up to and including the previously consumed token
+    pub fn span_from(&self, start: Span) -> Span {
+        match self.pos.checked_sub(1).and_then(|p| self.tokens.get(p)) {
+            Some(t) => start.to(t.span),
+            None => start
         }
-        self.peek()
     }
     fn display(&self) {
-        let toks = self.tokens.clone();
         print!("{:<10}", format!("POS {} > ", self.pos));
-        for (i, val) in toks.iter().enumerate() {
-            let txt = match val {
-                Token::Data(d) => match d {
+        for (i, val) in self.tokens.iter().enumerate() {
+            let txt = match &val.kind {
+                TokenKind::Data(d) => match d {
                     Number::Int(i) => i.to_string(),
                     Number::Float(i) => i.to_string(),
+                    Number::Char(c) => format!("{c:?}"),
+                    Number::Big(n) => n.to_string(),
                     Number::Thought => "Thought".to_string(),
                 }
-                Token::OpToken(o) => o.to_char().to_string(),
-                Token::Keyword(k) => k.to_str().to_string(),
-                _ => format!("{:?}", val)
+                TokenKind::OpToken(o) => o.to_str().to_string(),
+                TokenKind::Keyword(k) => k.to_str().to_string(),
+                kind => format!("{:?}", kind)
             };
 
             if i == self.pos {
@@ -142,4 +168,4 @@ impl<'t> Parser<'t> {
         }
         println!();
     }
-}
\ No newline at end of file
+}
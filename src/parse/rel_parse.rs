@@ -1,171 +1,206 @@
-use crate::parse::{ExprType, Node};
-use crate::parse::parser::{Parser};
-use crate::tokenize::token::Token;
-use crate::util::err::err_code;
+use crate::parse::{ExprType, LoopKind, Macro, Node, NodeKind};
+use crate::parse::parser::Parser;
+use crate::tokenize::token::TokenKind;
+use crate::util::err::{NaquaError, NaquaResult, ParseError};
+use crate::util::types::{Keyword, Number, Operator};
 
 impl<'t> Parser<'t> {
-    pub fn parse_if(&mut self) -> Result<Option<Node>, String> {
+    pub fn parse_if(&mut self) -> NaquaResult<Option<Node>> {
+        let start = self.span();
         self.advance();
 
-        let cond = match self.peek() {
-            Some(c) => match self.parse_num(0, match c {
-                Token::Data(n) => Token::Data(*n),
-                _ => return Err(self.err("Expected a number for if statement condition"))
-            }) {
-                Ok(Some(n)) => n,
-                Ok(None) => return Err(err_code(101)),
-                Err(e) => return Err(e)
-            },
-            None => {
-                self.undo();
-                return Err(self.err("Expected a number for if statement condition"));
+        let cond = self.parse_num("as the 'if' condition")?;
+        // `if N { }` with a literal number N is shorthand for `if thought == N { }`;
+        // any other condition is tested for truthiness, like in `while`
+        let cond = match cond.kind {
+            NodeKind::Literal(Number::Thought) => cond,
+            NodeKind::Literal(_) => {
+                let span = cond.span;
+                let thought = Node::new(NodeKind::Literal(Number::Thought), span);
+                Node::new(NodeKind::Eval(Box::new(thought), Operator::Eq, Box::new(cond)), span)
             }
+            _ => cond
         };
 
-        let mut body = Vec::new();
+        let body = self.parse_block("an if statement")?;
+
+        // `else` may sit on the line after the closing brace
+        let before_else = self.pos;
+        self.skip_newlines();
+        let otherwise = match self.peek() {
+            Some(TokenKind::Keyword(Keyword::Else)) => {
+                if self.log { println!("Else found!"); }
+                self.advance();
+                match self.peek() {
+                    Some(TokenKind::Keyword(Keyword::If)) => self.parse_if()?.into_iter().collect(),
+                    _ => self.parse_block("an else branch")?
+                }
+            }
+            _ => {
+                self.pos = before_else;
+                Vec::new()
+            }
+        };
 
+        let span = start.to(cond.span);
+        Ok(Some(Node::new(NodeKind::If(Box::new(cond), body, otherwise), span)))
+    }
+    pub fn parse_loop(&mut self, label: Option<String>) -> NaquaResult<Option<Node>> {
+        let start = self.span();
         self.advance();
 
-        if let Some(Token::OpenBrace) = self.peek() {
-            let mut brace_count = 1;
-            self.advance();
+        let body = self.parse_loop_body(&label, "a loop")?;
 
-            while let Some(tok) = self.peek().cloned() {
-                match tok {
-                    Token::OpenBrace => {
-                        brace_count += 1;
-                        self.advance();
-                    },
-                    Token::CloseBrace => {
-                        brace_count -= 1;
-                        if brace_count == 0 {
-                            self.advance();
-                            break;
-                        }
-                    },
-                    Token::NewLine => {
-                        self.advance();
-                    },
-                    _ => {
-                        match self.parse_expression() {
-                            Ok(Some(node)) => match node {
-                                ExprType::Node(n) => if let Some(m) = n { body.push(m) },
-                                ExprType::Macro(_) => return Err("Macro definitions are not allowed in if statements!".to_string()),
-                            },
-                            Ok(None) => {}, // Continue to next token
-                            Err(e) => return Err(e), // Propagate the error
-                        }
-                    }
-                }
-            }
-            if brace_count != 0 {
-                return Err(self.err("Mismatched braces in if statement"));
-            }
-        } else {
-            return Err(self.err("Expected opening brace for if statement"));
-        }
+        Ok(Some(Node::new(NodeKind::Loop { label, kind: LoopKind::Forever, body }, self.span_from(start))))
+    }
+    pub fn parse_while(&mut self, label: Option<String>) -> NaquaResult<Option<Node>> {
+        let start = self.span();
+        self.advance();
 
-        Ok(Some(Node::If(Box::new(cond), Box::new(body))))
+        let cond = self.parse_num("as the 'while' condition")?;
+        let body = self.parse_loop_body(&label, "a while loop")?;
+
+        let span = start.to(cond.span);
+        Ok(Some(Node::new(NodeKind::Loop { label, kind: LoopKind::While(Box::new(cond)), body }, span)))
     }
-    pub fn parse_loop(&mut self) -> Result<Option<Node>, String> {
+    pub fn parse_repeat(&mut self, label: Option<String>) -> NaquaResult<Option<Node>> {
+        let start = self.span();
         self.advance();
 
-        let mut body = Vec::new();
+        let count = self.parse_num("as the 'repeat' count")?;
+        let body = self.parse_loop_body(&label, "a repeat loop")?;
 
-        if let Some(Token::OpenBrace) = self.peek() {
-            let mut brace_count = 1;
-            self.advance();
+        let span = start.to(count.span);
+        Ok(Some(Node::new(NodeKind::Loop { label, kind: LoopKind::Repeat(Box::new(count)), body }, span)))
+    }
+    // `for <cell> from <first> to <last> { ... }`
+    pub fn parse_for(&mut self, label: Option<String>) -> NaquaResult<Option<Node>> {
+        let start = self.span();
+        self.advance();
 
-            while let Some(tok) = self.peek() {
-                match tok {
-                    Token::OpenBrace => {
-                        brace_count += 1;
-                        self.advance();
-                    },
-                    Token::CloseBrace => {
-                        brace_count -= 1;
-                        if brace_count == 0 {
-                            self.advance();
-                            break;
-                        }
-                    },
-                    _ => {
-                        match self.parse_expression() {
-                            Ok(Some(node)) => match node {
-                                ExprType::Node(n) => if let Some(m) = n { body.push(m) },
-                                ExprType::Macro(_) => return Err(self.err("Macro definitions are not allowed in loop statements!")),
-                            },
-                            Ok(None) => {}, // Continue to next token
-                            Err(e) => return Err(e), // Propagate the error
-                        }
-                    }
-                }
-            }
-            if brace_count != 0 {
-                return Err(self.err("Mismatched braces in loop statement"));
-            }
-        } else {
-            return Err(self.err("Expected opening brace for loop statement"));
-        }
+        let cell = self.parse_num("as the 'for' counter cell")?;
+        self.expect_keyword(Keyword::From)?;
+        let first = self.parse_num("after 'from'")?;
+        self.expect_keyword(Keyword::To)?;
+        let last = self.parse_num("after 'to'")?;
+        let body = self.parse_loop_body(&label, "a for loop")?;
 
-        Ok(Some(Node::Loop(Box::new(body))))
+        let span = start.to(last.span);
+        let kind = LoopKind::For(Box::new(cell), Box::new(first), Box::new(last));
+        Ok(Some(Node::new(NodeKind::Loop { label, kind, body }, span)))
+    }
+    // `<label>: <loop>`
+    pub fn parse_labeled(&mut self) -> NaquaResult<Option<Node>> {
+        let start = self.span();
+        let label = match self.peek() {
+            Some(TokenKind::RTKeyword(s)) => s.clone(),
+            found => return Err(self.err(ParseError::UnexpectedToken(found.cloned().unwrap_or(TokenKind::NewLine))))
+        };
+        self.advance(); // Move past the label
+        self.advance(); // Move past the ':'
+        self.skip_newlines();
+
+        match self.peek() {
+            Some(TokenKind::Keyword(Keyword::Loop)) => self.parse_loop(Some(label)),
+            Some(TokenKind::Keyword(Keyword::While)) => self.parse_while(Some(label)),
+            Some(TokenKind::Keyword(Keyword::Repeat)) => self.parse_repeat(Some(label)),
+            Some(TokenKind::Keyword(Keyword::For)) => self.parse_for(Some(label)),
+            _ => Err(NaquaError::Parse(ParseError::MisplacedLabel(label), start))
+        }
+    }
+    // Loop bodies are parsed with their label in scope, so `break`/`continue` can be checked against it
+    fn parse_loop_body(&mut self, label: &Option<String>, construct: &'static str) -> NaquaResult<Vec<Node>> {
+        self.loops.push(label.clone());
+        let body = self.parse_block(construct);
+        self.loops.pop();
+        body
     }
-    pub fn parse_def(&mut self) -> Result<(String, Box<Vec<Node>>), String> {
+    pub fn parse_def(&mut self) -> NaquaResult<(String, Macro)> {
         self.advance();
 
+        let span = self.span();
         let name = match self.peek().cloned() {
-            Some(Token::RTKeyword(c)) => {
+            Some(TokenKind::RTKeyword(c)) => {
                 c
             }
-            Some(_) | None => {
-                self.undo();
-                return Err(self.err("'define' statement needs a name!"));
+            found => {
+                return Err(self.err(ParseError::ExpectedMacroName { after: "define", found }));
             }
         };
 
-        let mut body = Vec::new();
+        self.advance();
+
+        let params = self.parse_params()?;
 
+        // A macro body is not inside the loops around its definition
+        let loops = std::mem::take(&mut self.loops);
+        self.params = Some(params);
+        let body = self.parse_block("a macro definition");
+        self.loops = loops;
+        let params = self.params.take().unwrap_or_default();
+        let body = body?;
+
+        Ok((name, Macro { params, body, span }))
+    }
+    // Optional `(a, b, ...)` after a macro name
+    fn parse_params(&mut self) -> NaquaResult<Vec<String>> {
+        let mut params = Vec::new();
+        if self.peek() != Some(&TokenKind::OpenParen) {
+            return Ok(params);
+        }
+        let open = self.span();
         self.advance();
 
-        if let Some(Token::OpenBrace) = self.peek() {
-            let mut brace_count = 1;
+        if self.peek() == Some(&TokenKind::CloseParen) {
             self.advance();
-
-            while let Some(tok) = self.peek().cloned() {
-                match tok {
-                    Token::OpenBrace => {
-                        brace_count += 1;
-                        self.advance();
-                    },
-                    Token::CloseBrace => {
-                        brace_count -= 1;
-                        if brace_count == 0 {
-                            self.advance();
-                            break;
-                        }
-                    },
-                    Token::NewLine => {
-                        self.advance();
-                    },
-                    _ => {
-                        match self.parse_expression() {
-                            Ok(Some(node)) => match node {
-                                ExprType::Node(n) => if let Some(m) = n { body.push(m) },
-                                ExprType::Macro(_) => return Err(self.err("Macro definitions are not allowed to be in other macro definitions!")),
-                            },
-                            Ok(None) => {}, // Continue to next token
-                            Err(e) => return Err(e), // Propagate the error
-                        }
+            return Ok(params);
+        }
+        loop {
+            match self.peek().cloned() {
+                Some(TokenKind::RTKeyword(name)) => {
+                    if params.contains(&name) {
+                        return Err(self.err(ParseError::DuplicateParam(name)));
                     }
+                    params.push(name);
+                    self.advance();
                 }
+                found => return Err(self.err(ParseError::ExpectedParamName(found)))
             }
-            if brace_count != 0 {
-                return Err(self.err("Mismatched braces in macro definition at expression #{}"));
+            match self.peek() {
+                Some(TokenKind::Comma) => { self.advance(); }
+                Some(TokenKind::CloseParen) => {
+                    self.advance();
+                    return Ok(params);
+                }
+                _ => return Err(NaquaError::Parse(ParseError::UnclosedParen, open))
             }
-        } else {
-            return Err(self.err("Expected opening brace for macro definition at expression #{}"));
+        }
+    }
+    // Parses the statements of a `{ ... }` block; `construct` names what the block belongs to
+    pub fn parse_block(&mut self, construct: &'static str) -> NaquaResult<Vec<Node>> {
+        let open = self.span();
+        match self.peek() {
+            Some(TokenKind::OpenBrace) => { self.advance(); }
+            found => return Err(self.err(ParseError::ExpectedBlock { construct, found: found.cloned() }))
         }
 
-        Ok((name, Box::new(body)))
+        let mut body = Vec::new();
+        loop {
+            self.skip_newlines();
+            let statement = self.span();
+            match self.peek() {
+                Some(TokenKind::CloseBrace) => {
+                    self.advance();
+                    return Ok(body);
+                }
+                None => return Err(NaquaError::Parse(ParseError::UnclosedBlock(construct), open)),
+                Some(_) => match self.parse_expression()? {
+                    Some(ExprType::Node(Some(node))) => body.push(node),
+                    Some(ExprType::Macro(_)) => return Err(NaquaError::Parse(ParseError::NestedDefine(construct), statement)),
+                    Some(ExprType::Include(_)) => return Err(NaquaError::Parse(ParseError::MisplacedInclude(construct), statement)),
+                    Some(ExprType::Node(None)) | None => {}
+                }
+            }
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,68 @@
+use std::collections::{HashMap, HashSet};
+use crate::parse::{Macro, Node, NodeKind, ParserResult};
+use crate::util::err::{NaquaError, NaquaResult, NaquaWarning, ParseError};
+use crate::util::span::Span;
+
+pub struct Program {
+    pub nodes: Vec<Node>,
+    pub macros: HashMap<String, Macro>,
+    pub warnings: Vec<NaquaWarning>,
+}
+
+// Checks the macros of a fully loaded program: every name is defined once, and every `run`/`spawn` refers
+// to a defined macro. Macros of the script itself (file 0) that are never called are reported as warnings.
+// `files` are the loaded (name, source) pairs, used to say which file a duplicate was first defined in.
+// `known` tells whether a macro defined outside the program, like in an earlier REPL entry, exists
+pub fn resolve(ast: ParserResult, files: &[(String, String)], known: impl Fn(&str) -> bool) -> NaquaResult<Program> {
+    let mut macros: HashMap<String, Macro> = HashMap::new();
+    let mut order = Vec::new();
+    for (name, mac) in ast.macros {
+        if let Some(first) = macros.get(&name) {
+            let first = format!("{}:{}:{}", files[first.span.file].0, first.span.line, first.span.col);
+            return Err(NaquaError::Parse(ParseError::DuplicateMacro { name, first }, mac.span));
+        }
+        order.push(name.clone());
+        macros.insert(name, mac);
+    }
+
+    // (caller, callee, location of the call); top-level calls have no caller
+    let mut calls: Vec<(Option<String>, String, Span)> = Vec::new();
+    for node in &ast.nodes {
+        collect_calls(node, None, &mut calls);
+    }
+    for (name, mac) in &macros {
+        for node in &mac.body {
+            collect_calls(node, Some(name), &mut calls);
+        }
+    }
+    calls.sort_by_key(|(_, _, span)| (span.file, span.start));
+
+    let mut used = HashSet::new();
+    for (caller, callee, span) in calls {
+        if !macros.contains_key(&callee) && !known(&callee) {
+            return Err(NaquaError::Parse(ParseError::UndefinedMacro(callee), span));
+        }
+        // A macro calling itself doesn't make it used
+        if caller.as_ref() != Some(&callee) {
+            used.insert(callee);
+        }
+    }
+
+    let warnings = order.into_iter()
+        .filter(|name| !used.contains(name) && macros[name].span.file == 0)
+        .map(|name| {
+            let span = macros[&name].span;
+            NaquaWarning::UnusedMacro(name, span)
+        })
+        .collect();
+
+    Ok(Program { nodes: ast.nodes, macros, warnings })
+}
+
+fn collect_calls(node: &Node, caller: Option<&String>, calls: &mut Vec<(Option<String>, String, Span)>) {
+    node.visit(&mut |n: &Node| {
+        if let NodeKind::Run(name, _) | NodeKind::Spawn(name, _) = &n.kind {
+            calls.push((caller.cloned(), name.clone(), n.span));
+        }
+    });
+}
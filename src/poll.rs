@@ -1,60 +1,276 @@
 use std::fs;
+use std::io::{self, Read};
 use std::path::Path;
-use crate::parse::parser::Parser;
-use crate::runtime::runtime::Runner;
+use std::sync::Arc;
+use crate::format::format;
+use crate::module::Loader;
+use crate::parse::dump::dump;
+use crate::parse::optimize::optimize;
+use crate::parse::resolve::{resolve, Program};
+use crate::repl::Repl;
+use crate::runtime::op_eval::Arith;
+use crate::runtime::runtime::{Runner, STACK_SIZE};
 use crate::tokenize::tokenizer::Tokenizer;
+use crate::util::diagnostic;
+use crate::util::err::NaquaResult;
+use crate::util::types::Number;
+use crate::vm::compiler::Compiler;
+use crate::vm::machine::Vm;
 
-pub fn poll() -> Result<(), &'static str>{
-    let args = std::env::args().collect::<Vec<String>>();
-    let input;
-    let mut should_log = args.contains(&String::from("-l"));
-
-    if !(2..3).contains(&args.len()) {
-        if Path::new("test.naq").exists() {
-            input = fs::read_to_string("test.naq").unwrap();
-        } else {
-            println!("Incorrect usage!");
-            println!("Correct usage: naqua <filename> <flags>");
-            return Err("Incorrect usage!");
-        }
-    } else {
-        input = fs::read_to_string(args.get(1)
-            .ok_or("No path found! If you need to run it without arguments, \
-                add a file 'test.naq' in Naqua's root directory!")?)
-            .map_err(|_| "Failed to read file!")?;
-    }
-
-    // should_log = true;
-
-    println!();
-
-    let mut tokenizer = Tokenizer::new(input);
-    match tokenizer.tokenize() {
-        Ok(tokens) => {
-            if should_log { println!("Tokens: \n{:?}\n", tokens); }
-            let mut parser = Parser::new(&tokens, should_log);
-            match parser.parse() {
-                Ok(ast) => {
-                    if should_log {
-                        for mac in &ast.macros {
-                            println!("MACRO {:?}", mac);
-                        }
-                        for node in &ast.nodes {
-                            println!("{:?}", node);
-                        }
-                        if should_log { println!(); }
-                    }
-                    let mut rt = Runner::new();
-                    match rt.run(ast.nodes, ast.macros) {
-                        Ok(_) => {}
-                        Err(e) => eprintln!("Runtime error: {e}")
+const USAGE: &str = "\
+Usage: naqua [command] <file | - | -e <code>> [flags] [-- <script args>...]
+
+Commands:
+  run         run the script (the default)
+  check       look for errors without running the script
+  tokens      print the script's tokens
+  ast         print the script's syntax tree, as it will be run
+  fmt         print the script re-indented; --write saves it instead
+  repl        start an interactive session
+
+Flags:
+  -e <code>   run <code> instead of a file
+  -           read the script from stdin
+  -l          log tokens, AST and bytecode
+  --vm        run on the bytecode VM
+  --no-opt    skip the optimizer
+  --arith <mode>
+              what integers do past 64 bits: checked (an error, the default),
+              wrapping, saturating, or big (switch to unbounded integers)
+  --write     (fmt) write the result back to the file
+  -h, --help  show this message
+
+Everything after '--' is passed to the script, which reads it with 'arg <n>' and 'args'";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Run,
+    Check,
+    Tokens,
+    Ast,
+    Fmt,
+    Repl,
+}
+impl Command {
+    fn from(input: &str) -> Option<Command> {
+        match input {
+            "run" => Some(Command::Run),
+            "check" => Some(Command::Check),
+            "tokens" => Some(Command::Tokens),
+            "ast" => Some(Command::Ast),
+            "fmt" => Some(Command::Fmt),
+            "repl" => Some(Command::Repl),
+            _ => None
+        }
+    }
+}
+
+enum Source {
+    File(String),
+    Stdin,
+    Inline(String),
+}
+
+struct Options {
+    command: Command,
+    source: Option<Source>,
+    log: bool,
+    vm: bool,
+    optimize: bool,
+    arith: Arith,
+    write: bool,
+    help: bool,
+    script_args: Vec<String>,
+}
+impl Options {
+    // `args` are the command-line arguments after the program name
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            command: Command::Run,
+            source: None,
+            log: false,
+            vm: false,
+            optimize: true,
+            arith: Arith::Checked,
+            write: false,
+            help: false,
+            script_args: Vec::new(),
+        };
+        let mut command = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let source = match arg.as_str() {
+                "--" => {
+                    options.script_args = args.collect();
+                    break;
+                }
+                "-l" => { options.log = true; continue; }
+                "--vm" => { options.vm = true; continue; }
+                "--no-opt" => { options.optimize = false; continue; }
+                "--arith" => {
+                    let mode = args.next().ok_or("'--arith' needs a mode after it")?;
+                    options.arith = Arith::from(&mode).ok_or_else(|| format!("Unknown arithmetic mode '{mode}', expected checked, wrapping, saturating or big"))?;
+                    continue;
+                }
+                "--write" => { options.write = true; continue; }
+                "-h" | "--help" => { options.help = true; continue; }
+                "-e" => Source::Inline(args.next().ok_or("'-e' needs the code to run after it")?),
+                "-" => Source::Stdin,
+                flag if flag.starts_with('-') => return Err(format!("Unknown flag '{flag}'")),
+                // The command comes first; a script named like one can be given as `./<name>`
+                word if command.is_none() && options.source.is_none() && Command::from(word).is_some() => {
+                    command = Command::from(word);
+                    continue;
+                }
+                _ => Source::File(arg),
+            };
+            if options.source.is_some() {
+                return Err("Only one script can be given".to_string());
+            }
+            options.source = Some(source);
+        }
+        options.command = command.unwrap_or(Command::Run);
+        Ok(options)
+    }
+}
+
+// Runs the command line and returns the status to exit with; `Err` is a command-line mistake
+pub fn poll() -> Result<i32, String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let no_args = args.is_empty();
+    let mut options = Options::parse(args)?;
+
+    if options.help {
+        println!("{USAGE}");
+        return Ok(0);
+    }
+    if options.command == Command::Repl {
+        if options.source.is_some() {
+            return Err("'repl' doesn't take a script, use ':load <file>' inside it".to_string());
+        }
+        Repl::new(options.log, options.optimize, options.arith).run();
+        return Ok(0);
+    }
+    if no_args && Path::new("test.naq").exists() {
+        options.source = Some(Source::File(String::from("test.naq")));
+    }
+
+    let (name, input) = match &options.source {
+        Some(Source::File(path)) => (path.clone(), fs::read_to_string(path).map_err(|e| format!("Failed to read '{path}': {e}"))?),
+        Some(Source::Stdin) => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input).map_err(|e| format!("Failed to read stdin: {e}"))?;
+            (String::from("<stdin>"), input)
+        }
+        Some(Source::Inline(code)) => (String::from("<-e>"), code.clone()),
+        None => {
+            println!("{USAGE}");
+            return Err("No script given!".to_string());
+        }
+    };
+    let script_args = options.script_args.iter()
+        .map(|a| parse_number(a).ok_or_else(|| format!("Script argument '{a}' is not a number")))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match options.command {
+        Command::Tokens => {
+            return Ok(match Tokenizer::new(input.clone(), 0).tokenize() {
+                Ok(tokens) => {
+                    tokens.iter().for_each(|t| println!("{:<8} {}", format!("{}:{}", t.span.line, t.span.col), t.kind));
+                    0
+                }
+                Err(e) => {
+                    eprint!("{}", diagnostic::render(&e, &name, &input));
+                    e.exit_code()
+                }
+            });
+        }
+        Command::Fmt => {
+            return Ok(match format(&input) {
+                Ok(formatted) => {
+                    match &options.source {
+                        Some(Source::File(path)) if options.write => fs::write(path, formatted).map_err(|e| format!("Failed to write '{path}': {e}"))?,
+                        _ if options.write => return Err("'--write' needs a script file".to_string()),
+                        _ => print!("{formatted}"),
                     }
+                    0
                 }
-                Err(e) => eprintln!("Parsing error: {e}")
+                Err(e) => {
+                    eprint!("{}", diagnostic::render(&e, &name, &input));
+                    e.exit_code()
+                }
+            });
+        }
+        _ => {}
+    }
+
+    if options.command == Command::Run { println!(); }
+
+    let mut loader = Loader::new(options.log);
+    let result = loader.load(Path::new(&name), input).and_then(|ast| resolve(ast, &loader.files, |_| false)).and_then(|mut program| {
+        for warning in &program.warnings {
+            let (name, source) = &loader.files[warning.span().file];
+            eprint!("{}", diagnostic::render_warning(warning, name, source));
+        }
+        if options.optimize && options.command != Command::Check {
+            optimize(&mut program);
+        }
+        match options.command {
+            Command::Check => {
+                println!("No errors found in {name}");
+                Ok(())
+            }
+            Command::Ast => {
+                print!("{}", dump(&program.nodes, &program.macros));
+                Ok(())
             }
+            _ => execute(program, &options, script_args)
+        }
+    });
+    let status = match result {
+        Ok(()) => 0,
+        Err(e) => {
+            // Errors are shown against the file they come from, which may be an included one
+            let (name, source) = &loader.files[e.span().file];
+            eprint!("{}", diagnostic::render(&e, name, source));
+            e.exit_code()
+        }
+    };
+    if options.command == Command::Run { println!(); }
+    Ok(status)
+}
+
+fn execute(program: Program, options: &Options, args: Vec<Number>) -> NaquaResult<()> {
+    if options.log {
+        for mac in &program.macros {
+            println!("MACRO {:?}", mac);
         }
-        Err(e) => eprintln!("Tokenization error: {e}")
+        for node in &program.nodes {
+            println!("{:?}", node);
+        }
+        println!();
     }
-    println!();
-    Ok(())
-}
\ No newline at end of file
+    if options.vm {
+        let code = Compiler::compile(&program.nodes, &program.macros);
+        if options.log {
+            for (i, instr) in code.code.iter().enumerate() {
+                println!("{i:>5} {instr:?}");
+            }
+            println!();
+        }
+        let mut vm = Vm::new(code);
+        vm.args = Arc::new(args);
+        vm.arith = options.arith;
+        return vm.run();
+    }
+    let mut rt = Runner::new();
+    rt.args = Arc::new(args);
+    rt.arith = options.arith;
+    // This is the interpreter thread `main` starts
+    rt.limit_stack(STACK_SIZE);
+    rt.run(program.nodes, program.macros)
+}
+
+fn parse_number(input: &str) -> Option<Number> {
+    input.parse().map(Number::Int).ok().or_else(|| input.parse().map(Number::Float).ok())
+}
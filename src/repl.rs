@@ -0,0 +1,155 @@
+use std::io::{self, Write};
+use std::fs;
+use std::path::Path;
+use crate::module::Loader;
+use crate::parse::optimize::optimize;
+use crate::parse::resolve::resolve;
+use crate::runtime::op_eval::Arith;
+use crate::runtime::runtime::{take_printed, Runner, STACK_SIZE};
+use crate::tokenize::token::TokenKind;
+use crate::tokenize::tokenizer::Tokenizer;
+use crate::util::diagnostic;
+use crate::util::types::Number;
+
+const HELP: &str = "\
+:stack          show every stored cell
+:thought        show the current thought
+:macros         list the defined macros
+:reset          forget the stack, thought and macros
+:load <file>    run a script in this session
+:help           show this list
+:quit           leave (Ctrl+D works too)";
+
+// Interactive session. Every entry runs on the same `Runner`, so the stack, thought and macros carry over
+pub struct Repl {
+    log: bool,
+    optimize: bool,
+    loader: Loader,
+    runner: Runner,
+}
+impl Repl {
+    pub fn new(log: bool, optimize: bool, arith: Arith) -> Self {
+        let mut runner = Runner::new();
+        runner.arith = arith;
+        Self { log, optimize, loader: Loader::new(log), runner }
+    }
+
+    pub fn run(&mut self) {
+        println!("Naqua REPL, :help lists the commands");
+        let mut entry = String::new();
+        loop {
+            print!("{}", if entry.is_empty() { "> " } else { "... " });
+            io::stdout().flush().ok();
+
+            // Stdin isn't held between lines, so scripts can read from it too
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+                break;
+            }
+            if entry.is_empty() && line.trim_start().starts_with(':') {
+                if !self.command(line.trim()) {
+                    return;
+                }
+                continue;
+            }
+
+            entry.push_str(&line);
+            // Keep reading until every `{` is closed
+            if open_blocks(&entry) > 0 {
+                continue;
+            }
+            let source = std::mem::take(&mut entry);
+            if !source.trim().is_empty() {
+                self.eval(Path::new("<repl>"), source);
+            }
+        }
+        println!();
+    }
+
+    // Runs a meta-command; returns `false` when the session should end
+    fn command(&mut self, line: &str) -> bool {
+        let (name, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match name {
+            ":stack" => {
+                let mut cells: Vec<_> = self.runner.stack.iter().collect();
+                cells.sort_by_key(|(i, _)| **i);
+                if cells.is_empty() {
+                    println!("(empty)");
+                }
+                for (i, n) in cells {
+                    println!("{i}: {}", show(n));
+                }
+            }
+            ":thought" => println!("{}", show(&self.runner.thought)),
+            ":macros" => {
+                let mut names: Vec<_> = self.runner.macros.iter()
+                    .map(|(name, m)| format!("{name}({})", m.params.join(", ")))
+                    .collect();
+                names.sort();
+                if names.is_empty() {
+                    println!("(none)");
+                }
+                names.iter().for_each(|n| println!("{n}"));
+            }
+            ":reset" => {
+                let arith = self.runner.arith;
+                self.runner = Runner::new();
+                self.runner.arith = arith;
+                self.loader = Loader::new(self.log);
+            }
+            ":load" => {
+                let path = arg.trim();
+                match fs::read_to_string(path) {
+                    Ok(source) => self.eval(Path::new(path), source),
+                    Err(e) => eprintln!("Failed to read '{path}': {e}"),
+                }
+            }
+            ":help" => println!("{HELP}"),
+            ":quit" | ":q" => return false,
+            _ => eprintln!("Unknown command '{name}', :help lists the commands"),
+        }
+        true
+    }
+
+    fn eval(&mut self, file: &Path, source: String) {
+        // Macros from earlier entries stay callable, and defining one again replaces it. Only the entry's own
+        // macros are resolved and optimized, so earlier ones aren't inlined into it
+        let result = self.loader.load(file, source).and_then(|ast| {
+            resolve(ast, &self.loader.files, |name| self.runner.macros.contains_key(name))
+        }).and_then(|mut program| {
+            // Unused macro warnings are left out: a macro is usually called in a later entry than its definition
+            if self.optimize {
+                optimize(&mut program);
+            }
+            take_printed();
+            // The session runs on the interpreter thread `main` starts
+            self.runner.limit_stack(STACK_SIZE);
+            let result = self.runner.run(program.nodes, program.macros);
+            // Ends the entry's output so the next prompt starts on its own line
+            if take_printed() {
+                println!();
+            }
+            result
+        });
+        if let Err(e) = result {
+            let (name, source) = &self.loader.files[e.span().file];
+            eprint!("{}", diagnostic::render(&e, name, source));
+        }
+    }
+}
+
+// How many `{` are still waiting for their `}`. Input that doesn't tokenize counts as complete, so its error shows up
+fn open_blocks(source: &str) -> i32 {
+    let Ok(tokens) = Tokenizer::new(source.to_string(), 0).tokenize() else {
+        return 0;
+    };
+    tokens.iter().map(|t| match t.kind {
+        TokenKind::OpenBrace => 1,
+        TokenKind::CloseBrace => -1,
+        _ => 0
+    }).sum()
+}
+
+fn show(n: &Number) -> String {
+    n.eval_type().map(|v| v.to_string()).unwrap_or_default()
+}
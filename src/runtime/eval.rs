@@ -1,62 +1,141 @@
 use std::fmt::{Display, Formatter};
-use crate::parse::Node;
-use crate::runtime::op_eval;
-use crate::runtime::runtime::Runner;
+use std::sync::Arc;
+use num_bigint::{BigInt, Sign};
+use crate::parse::{Node, NodeKind};
+use crate::runtime::input::{read_char, read_number};
+use crate::runtime::op_eval::{convert, negate};
+use crate::runtime::runtime::{Flow, Runner};
+use crate::util::err::{NaquaResult, RuntimeError};
 use crate::util::types::Number;
 
 impl Runner {
-    pub fn eval(&mut self, node: &Node) -> Result<EvalType, String> {
-        let result = match node {
-            Node::Literal(n) => {
+    pub fn eval(&mut self, node: &Node) -> NaquaResult<EvalType> {
+        let result = match &node.kind {
+            NodeKind::Literal(n) => {
                 match n {
                     Number::Int(n) => {
-                        EvalType::Int(n.clone())
+                        EvalType::Int(*n)
                     },
                     Number::Float(n) => {
-                        EvalType::Float(n.clone())
+                        EvalType::Float(*n)
                     },
+                    Number::Char(c) => EvalType::Char(*c),
+                    Number::Big(n) => EvalType::Big(Arc::clone(n)),
                     Number::Thought => self.thought.eval_type().unwrap(),
                 }
             }
-            Node::Char(n) => {
-                match self.eval(n)? {
-                    EvalType::Int(n) => EvalType::Char(charify(n)),
-                    EvalType::Float(n) => EvalType::Char(charify(n.floor() as i64)),
-                    EvalType::Char(c) => EvalType::Char(c)
-                }
+            NodeKind::Char(n) => {
+                let v = self.eval(n)?;
+                self.span = node.span;
+                v.to_char().map_err(|e| self.err(e))?
             }
-            Node::Eval(l, o, r) => {
-                self.op_eval(l.clone(), *o, r.clone())?
+            NodeKind::Eval(l, o, r) => self.op_eval(l, *o, r, node.span)?,
+            NodeKind::Not(n) => EvalType::from_bool(!self.eval(n)?.is_truthy()),
+            NodeKind::Convert(c, n) => {
+                let v = self.eval(n)?;
+                self.span = node.span;
+                convert(v, *c, self.arith).map_err(|e| self.err(e))?
             }
-            Node::Out(n) => {
-                let index = match self.eval(n)? {
-                    EvalType::Int(n) => n,
-                    EvalType::Float(n) => n as i64,
-                    EvalType::Char(c) => c as i64,
-                };
-
+            NodeKind::Negate(n) => {
+                let v = self.eval(n)?;
+                self.span = node.span;
+                negate(v, self.arith).map_err(|e| self.err(e))?
+            }
+            NodeKind::Out(n) => {
+                let index = self.eval(n)?.to_index();
                 self.stack.get(&index).unwrap_or(&Number::Int(0)).eval_type().unwrap()
             }
+            NodeKind::Arg(n) => {
+                let index = self.eval(n)?.to_index();
+                usize::try_from(index).ok().and_then(|i| self.args.get(i)).unwrap_or(&Number::Int(0)).eval_type().unwrap()
+            }
+            NodeKind::ArgCount => EvalType::Int(self.args.len() as i64),
+            NodeKind::Read => {
+                self.span = node.span;
+                read_number().map_err(|e| self.err(e))?
+            }
+            NodeKind::ReadChar => {
+                self.span = node.span;
+                read_char().map_err(|e| self.err(e))?
+            }
+            NodeKind::Param(i) => {
+                self.frames.last().and_then(|f| f.get(*i)).unwrap_or(&Number::Int(0)).eval_type().unwrap()
+            }
+            NodeKind::Run(name, args) => {
+                self.span = node.span;
+                match self.call(name, args)? {
+                    Flow::Return(Some(value)) => value,
+                    _ => {
+                        self.span = node.span;
+                        return Err(self.err(RuntimeError::NoReturnValue(name.clone())));
+                    }
+                }
+            }
             _ => EvalType::Int(0)
         };
         Ok(result)
     }
 }
-fn charify(i: i64) -> char {
-    std::char::from_u32(i.rem_euclid(0x10FFFF) as u32).unwrap()
-}
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum EvalType {
     Int(i64),
     Float(f64),
     Char(char),
+    Big(Arc<BigInt>),
 }
 impl EvalType {
-    pub fn to_num(&self) -> Result<Number, ()> {
-        match self {
-            EvalType::Int(i) => Ok(Number::Int(*i)),
-            EvalType::Float(i) => Ok(Number::Float(*i)),
-            EvalType::Char(_) => { Err(()) },
+    // Converts into storable data
+    pub fn to_num(&self) -> Number {
+        match *self {
+            EvalType::Int(i) => Number::Int(i),
+            EvalType::Float(i) => Number::Float(i),
+            EvalType::Char(c) => Number::Char(c),
+            EvalType::Big(ref n) => Number::Big(Arc::clone(n)),
+        }
+    }
+    // Character with this value as its code point; floats are floored. Anything that isn't a
+    // Unicode scalar value (negative, a surrogate or past U+10FFFF) is an error
+    pub fn to_char(&self) -> Result<EvalType, RuntimeError> {
+        let code = match *self {
+            EvalType::Char(c) => return Ok(EvalType::Char(c)),
+            EvalType::Int(n) => Some(n),
+            EvalType::Float(n) => Some(n.floor()).filter(|f| f.is_finite()).map(|f| f as i64),
+            EvalType::Big(_) => None,
+        };
+        code.and_then(|n| u32::try_from(n).ok())
+            .and_then(char::from_u32)
+            .map(EvalType::Char)
+            .ok_or_else(|| RuntimeError::InvalidChar(self.to_string()))
+    }
+    // Process exit code with this value; floats are floored. Only 0 to 255 can be reported to the system,
+    // so anything else is an error rather than being cut down to its low byte
+    pub fn to_exit_code(&self) -> Result<i32, RuntimeError> {
+        match *self {
+            EvalType::Big(_) => None,
+            _ => Some(self.to_index()),
+        }.and_then(|n| u8::try_from(n).ok())
+            .map(i32::from)
+            .ok_or_else(|| RuntimeError::InvalidExitCode(self.to_string()))
+    }
+    pub fn from_bool(b: bool) -> EvalType {
+        EvalType::Int(b as i64)
+    }
+    // Nonzero values are true
+    pub fn is_truthy(&self) -> bool {
+        match *self {
+            EvalType::Int(n) => n != 0,
+            EvalType::Float(n) => n != 0.,
+            EvalType::Char(c) => c != '\0',
+            EvalType::Big(_) => true,
+        }
+    }
+    // Stack index addressed by this value; floats are floored, and big integers clamp to the ends
+    pub fn to_index(&self) -> i64 {
+        match *self {
+            EvalType::Int(n) => n,
+            EvalType::Float(n) => n.floor() as i64,
+            EvalType::Char(c) => c as i64,
+            EvalType::Big(ref n) => if n.sign() == Sign::Minus { i64::MIN } else { i64::MAX },
         }
     }
 }
@@ -65,7 +144,8 @@ impl Display for EvalType {
         match self {
             EvalType::Int(x) => write!(f, "{x}"),
             EvalType::Float(x) => write!(f, "{x}"),
-            EvalType::Char(x) => write!(f, "{x}")
+            EvalType::Char(x) => write!(f, "{x}"),
+            EvalType::Big(x) => write!(f, "{x}")
         }
     }
-}
\ No newline at end of file
+}
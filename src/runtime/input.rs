@@ -0,0 +1,93 @@
+use std::io::{self, BufRead, Write};
+use crate::runtime::eval::EvalType;
+use crate::util::err::RuntimeError;
+
+// What `read` and `readchar` give once stdin has run out
+pub const EOF: i64 = -1;
+
+// Both readers go through the buffer std keeps for stdin, so they can be mixed freely, even across threads
+
+// The next whitespace-separated word on stdin, as a number. The whitespace after it is left for `readchar`
+pub fn read_number() -> Result<EvalType, RuntimeError> {
+    prompt();
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut word = Vec::new();
+    loop {
+        let buf = input.fill_buf().map_err(read_failed)?;
+        if buf.is_empty() {
+            break;
+        }
+        let mut used = 0;
+        let mut ended = false;
+        for &b in buf {
+            if b.is_ascii_whitespace() {
+                if !word.is_empty() {
+                    ended = true;
+                    break;
+                }
+            } else {
+                word.push(b);
+            }
+            used += 1;
+        }
+        input.consume(used);
+        if ended {
+            break;
+        }
+    }
+    if word.is_empty() {
+        return Ok(EvalType::Int(EOF));
+    }
+
+    let word = String::from_utf8_lossy(&word);
+    word.parse().map(EvalType::Int)
+        .or_else(|_| word.parse().map(EvalType::Float))
+        .map_err(|_| RuntimeError::NotANumber(word.to_string()))
+}
+
+// Code point of the next character on stdin; bytes that aren't valid UTF-8 read as U+FFFD
+pub fn read_char() -> Result<EvalType, RuntimeError> {
+    prompt();
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let Some(first) = next_byte(&mut input)? else {
+        return Ok(EvalType::Int(EOF));
+    };
+
+    // The first byte of a UTF-8 sequence tells how long it is
+    let len = match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1
+    };
+    let mut bytes = vec![first];
+    while bytes.len() < len {
+        match next_byte(&mut input)? {
+            Some(b) => bytes.push(b),
+            None => break
+        }
+    }
+    let c = std::str::from_utf8(&bytes).ok()
+        .and_then(|s| s.chars().next())
+        .unwrap_or(char::REPLACEMENT_CHARACTER);
+    Ok(EvalType::Int(c as i64))
+}
+
+// Shows anything printed so far, like a question, before waiting for the answer
+fn prompt() {
+    let _ = io::stdout().flush();
+}
+
+fn next_byte(input: &mut impl BufRead) -> Result<Option<u8>, RuntimeError> {
+    let byte = input.fill_buf().map_err(read_failed)?.first().copied();
+    if byte.is_some() {
+        input.consume(1);
+    }
+    Ok(byte)
+}
+
+fn read_failed(e: io::Error) -> RuntimeError {
+    RuntimeError::ReadFailed(e.to_string())
+}
@@ -1,4 +1,5 @@
+#[allow(clippy::module_inception)]
 pub mod runtime;
 pub mod eval;
-mod op_eval;
-
+pub mod op_eval;
+pub mod input;
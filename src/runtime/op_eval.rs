@@ -1,55 +1,258 @@
-use std::ops::Deref;
+use std::sync::Arc;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Pow, Signed, ToPrimitive, Zero};
 use crate::parse::Node;
 use crate::runtime::eval::EvalType;
 use crate::runtime::runtime::Runner;
-use crate::util::types::{Number, Operator, Operator::*};
+use crate::util::err::{NaquaResult, RuntimeError};
+use crate::util::span::Span;
+use crate::util::types::{Conversion, Number, Operator, Operator::*};
+
+// What integer arithmetic does with results that don't fit in 64 bits
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Arith {
+    // Stops with an overflow error
+    #[default]
+    Checked,
+    // Wraps around, like two's complement hardware
+    Wrapping,
+    // Sticks at the smallest or largest integer
+    Saturating,
+    // Switches to arbitrary-precision integers
+    Big,
+}
+impl Arith {
+    pub fn from(input: &str) -> Option<Arith> {
+        match input {
+            "checked" => Some(Arith::Checked),
+            "wrapping" => Some(Arith::Wrapping),
+            "saturating" => Some(Arith::Saturating),
+            "big" => Some(Arith::Big),
+            _ => None
+        }
+    }
+}
 
 impl Runner {
-    pub fn op_eval(&mut self, lhs: Box<Node>, op: Operator, rhs: Box<Node>) -> Result<EvalType, String> {
-        let mut eval_node = |node: &Node| -> Result<Number, String> {
-            match node {
-                Node::Out(n) => Ok(self.eval(&Node::Out(n.clone()))?.to_num().unwrap()),
-                Node::Literal(n) => match n {
-                    Number::Thought => Ok(self.thought),
-                    _ => Ok(*n)
-                },
-                Node::Eval(l, o, r) =>
-                    Ok(self.op_eval(
-                        l.clone(),
-                        *o,
-                        r.clone(),
-                    )?.to_num().unwrap()),
-                _ => Ok(Number::Int(0))
-            }
-        };
-
-        let l = eval_node(lhs.deref())?;
-        let r = eval_node(rhs.deref())?;
-
-        Ok(if l.is_int() && r.is_int() {
-            match op {
-                Add => EvalType::Int(l.int().unwrap() + r.int().unwrap()),
-                Sub => EvalType::Int(l.int().unwrap() - r.int().unwrap()),
-                Mul => EvalType::Int(l.int().unwrap() * r.int().unwrap()),
-                Div => {
-                    if r.float() == 0. { return Err(self.err("Attempted division by zero!")); }
-                    EvalType::Float(l.float() / r.float())
-                }
-                Mod => EvalType::Int(l.int().unwrap() % r.int().unwrap()),
-                Exp => EvalType::Int(l.int().unwrap().pow(r.int().unwrap() as u32))
+    pub fn op_eval(&mut self, lhs: &Node, op: Operator, rhs: &Node, span: Span) -> NaquaResult<EvalType> {
+        // `and`/`or` only evaluate their right side when the left side doesn't decide the result
+        if let And | Or = op {
+            let l = self.eval(lhs)?.is_truthy();
+            if l == (op == Or) {
+                return Ok(EvalType::from_bool(l));
             }
-        } else {
-            match op {
-                Add => EvalType::Float(l.float() + r.float()),
-                Sub => EvalType::Float(l.float() - r.float()),
-                Mul => EvalType::Float(l.float() * r.float()),
-                Div => {
-                    if r.float() == 0. { return Err(self.err("Attempted division by zero!")); }
-                    EvalType::Float(l.float() / r.float())
-                },
-                Mod => EvalType::Float(l.float() % r.float()),
-                Exp => EvalType::Float(l.float().powf(r.float()))
-            }
-        })
+            return Ok(EvalType::from_bool(self.eval(rhs)?.is_truthy()));
+        }
+
+        let l = self.eval(lhs)?;
+        let r = self.eval(rhs)?;
+        // Errors point at this operation, not at whichever operand was evaluated last
+        self.span = span;
+        arith(l.to_num(), op, r.to_num(), self.arith).map_err(|e| self.err(e))
+    }
+}
+
+// Applies an arithmetic or comparison operator to two stored values
+pub fn arith(l: Number, op: Operator, r: Number, mode: Arith) -> Result<EvalType, RuntimeError> {
+    if let (Some(a), Some(b)) = (l.int(), r.int()) {
+        return int_arith(a, op, b, mode);
+    }
+    if let (Some(a), Some(b)) = (big(&l), big(&r)) {
+        return big_arith(a, op, b);
+    }
+    let (a, b) = (l.float(), r.float());
+    Ok(match op {
+        Add => EvalType::Float(a + b),
+        Sub => EvalType::Float(a - b),
+        Mul => EvalType::Float(a * b),
+        Div => {
+            if b == 0. { return Err(RuntimeError::DivisionByZero); }
+            EvalType::Float(a / b)
+        },
+        IntDiv => {
+            if b == 0. { return Err(RuntimeError::DivisionByZero); }
+            EvalType::Float((a / b).trunc())
+        },
+        Mod => {
+            if b == 0. { return Err(RuntimeError::DivisionByZero); }
+            EvalType::Float(a % b)
+        },
+        Exp => EvalType::Float(a.powf(b)),
+        Eq => EvalType::from_bool(a == b),
+        Ne => EvalType::from_bool(a != b),
+        Lt => EvalType::from_bool(a < b),
+        Le => EvalType::from_bool(a <= b),
+        Gt => EvalType::from_bool(a > b),
+        Ge => EvalType::from_bool(a >= b),
+        And | Or => unreachable!("logical operators short-circuit before reaching here")
+    })
+}
+
+// Applies `int`, `float`, `floor`, `ceil`, `round` or `abs`. Everything but `float` gives an integer,
+// so a float too large for one overflows like arithmetic does
+pub fn convert(v: EvalType, conversion: Conversion, mode: Arith) -> Result<EvalType, RuntimeError> {
+    let n = match v {
+        EvalType::Char(c) => c as i64,
+        EvalType::Int(n) => n,
+        EvalType::Big(n) => return Ok(match conversion {
+            Conversion::Float => EvalType::Float(n.to_f64().unwrap_or(f64::NAN)),
+            Conversion::Abs => normalize(n.abs()),
+            _ => EvalType::Big(n),
+        }),
+        EvalType::Float(f) => {
+            let whole = match conversion {
+                Conversion::Float => return Ok(EvalType::Float(f)),
+                Conversion::Abs => return Ok(EvalType::Float(f.abs())),
+                Conversion::Int => f.trunc(),
+                Conversion::Floor => f.floor(),
+                Conversion::Ceil => f.ceil(),
+                Conversion::Round => f.round(),
+            };
+            return float_to_int(whole, conversion, mode);
+        }
+    };
+    match conversion {
+        Conversion::Float => Ok(EvalType::Float(n as f64)),
+        Conversion::Abs if n < 0 => negate(EvalType::Int(n), mode),
+        _ => Ok(EvalType::Int(n)),
+    }
+}
+
+// A whole float as an integer, if it fits; `i64::MAX as f64` rounds up to 2^63, which doesn't
+fn float_to_int(f: f64, conversion: Conversion, mode: Arith) -> Result<EvalType, RuntimeError> {
+    if f >= -(2f64.powi(63)) && f < 2f64.powi(63) {
+        return Ok(EvalType::Int(f as i64));
+    }
+    match mode {
+        Arith::Checked => Err(RuntimeError::Overflow(format!("{} {f}", conversion.to_str()))),
+        Arith::Big if f.is_finite() => Ok(normalize(BigInt::from_f64(f).unwrap_or_default())),
+        // Casts saturate, and NaN becomes 0
+        _ => Ok(EvalType::Int(f as i64)),
+    }
+}
+
+// `-v`, which overflows for the smallest integer just like subtraction does
+pub fn negate(v: EvalType, mode: Arith) -> Result<EvalType, RuntimeError> {
+    match v {
+        EvalType::Float(n) => Ok(EvalType::Float(-n)),
+        EvalType::Big(n) => Ok(normalize(-&*n)),
+        EvalType::Char(c) => Ok(EvalType::Int(-(c as i64))),
+        EvalType::Int(n) => int_arith(0, Sub, n, mode),
+    }
+}
+
+fn int_arith(a: i64, op: Operator, b: i64, mode: Arith) -> Result<EvalType, RuntimeError> {
+    let checked = match op {
+        Add => a.checked_add(b),
+        Sub => a.checked_sub(b),
+        Mul => a.checked_mul(b),
+        Div => {
+            if b == 0 { return Err(RuntimeError::DivisionByZero); }
+            return Ok(EvalType::Float(a as f64 / b as f64));
+        }
+        IntDiv => {
+            if b == 0 { return Err(RuntimeError::DivisionByZero); }
+            a.checked_div(b)
+        }
+        Mod => {
+            if b == 0 { return Err(RuntimeError::DivisionByZero); }
+            // Only `i64::MIN % -1` overflows, and its remainder is 0 all the same
+            return Ok(EvalType::Int(a.wrapping_rem(b)));
+        }
+        // A negative power is a fraction
+        Exp if b < 0 => return Ok(EvalType::Float((a as f64).powf(b as f64))),
+        Exp => pow(a, b as u64, i64::checked_mul),
+        Eq => return Ok(EvalType::from_bool(a == b)),
+        Ne => return Ok(EvalType::from_bool(a != b)),
+        Lt => return Ok(EvalType::from_bool(a < b)),
+        Le => return Ok(EvalType::from_bool(a <= b)),
+        Gt => return Ok(EvalType::from_bool(a > b)),
+        Ge => return Ok(EvalType::from_bool(a >= b)),
+        And | Or => unreachable!("logical operators short-circuit before reaching here")
+    };
+    if let Some(n) = checked {
+        return Ok(EvalType::Int(n));
+    }
+    Ok(EvalType::Int(match mode {
+        Arith::Checked => return Err(RuntimeError::Overflow(format!("{a} {op} {b}"))),
+        Arith::Big => return big_arith(BigInt::from(a), op, BigInt::from(b)),
+        Arith::Wrapping => match op {
+            Add => a.wrapping_add(b),
+            Sub => a.wrapping_sub(b),
+            Mul => a.wrapping_mul(b),
+            IntDiv => a.wrapping_div(b),
+            _ => pow(a, b as u64, |x, y| Some(x.wrapping_mul(y))).unwrap_or(0),
+        },
+        Arith::Saturating => match op {
+            Add => a.saturating_add(b),
+            Sub => a.saturating_sub(b),
+            Mul => a.saturating_mul(b),
+            IntDiv => a.saturating_div(b),
+            // Only an odd power keeps a negative base negative
+            _ => if a < 0 && b % 2 == 1 { i64::MIN } else { i64::MAX },
+        },
+    }))
+}
+
+// `base ^ exp` by repeated squaring with `mul`, which gives `None` once it overflows
+fn pow(mut base: i64, mut exp: u64, mul: impl Fn(i64, i64) -> Option<i64>) -> Option<i64> {
+    let mut result = 1i64;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul(result, base)?;
+        }
+        exp >>= 1;
+        if exp > 0 {
+            base = mul(base, base)?;
+        }
+    }
+    Some(result)
+}
+
+// Integer value of a stored number, for arithmetic on big integers
+fn big(n: &Number) -> Option<BigInt> {
+    match n {
+        Number::Big(n) => Some((**n).clone()),
+        n => n.int().map(BigInt::from),
+    }
+}
+
+fn big_arith(a: BigInt, op: Operator, b: BigInt) -> Result<EvalType, RuntimeError> {
+    Ok(match op {
+        Add => normalize(a + b),
+        Sub => normalize(a - b),
+        Mul => normalize(a * b),
+        Div => {
+            if b.is_zero() { return Err(RuntimeError::DivisionByZero); }
+            EvalType::Float(a.to_f64().unwrap_or(f64::NAN) / b.to_f64().unwrap_or(f64::NAN))
+        }
+        IntDiv => {
+            if b.is_zero() { return Err(RuntimeError::DivisionByZero); }
+            normalize(a / b)
+        }
+        Mod => {
+            if b.is_zero() { return Err(RuntimeError::DivisionByZero); }
+            normalize(a % b)
+        }
+        Exp => match b.to_u32() {
+            Some(e) => normalize(Pow::pow(&a, e)),
+            None if b < BigInt::ZERO => EvalType::Float(a.to_f64().unwrap_or(f64::NAN).powf(b.to_f64().unwrap_or(f64::NAN))),
+            None => return Err(RuntimeError::Overflow(format!("{a} {op} {b}"))),
+        },
+        Eq => EvalType::from_bool(a == b),
+        Ne => EvalType::from_bool(a != b),
+        Lt => EvalType::from_bool(a < b),
+        Le => EvalType::from_bool(a <= b),
+        Gt => EvalType::from_bool(a > b),
+        Ge => EvalType::from_bool(a >= b),
+        And | Or => unreachable!("logical operators short-circuit before reaching here")
+    })
+}
+
+// Big integers that fit in 64 bits go back to being plain integers
+fn normalize(n: BigInt) -> EvalType {
+    match n.to_i64() {
+        Some(n) => EvalType::Int(n),
+        None => EvalType::Big(Arc::new(n)),
     }
-}
\ No newline at end of file
+}
@@ -1,16 +1,55 @@
 use std::collections::HashMap;
-use std::ops::Deref;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use crate::parse::Node;
+use crate::parse::{LoopKind, Macro, Node, NodeKind};
+use crate::runtime::eval::EvalType;
+use crate::runtime::op_eval::Arith;
+use crate::util::err::{NaquaError, NaquaResult, RuntimeError};
+use crate::util::span::Span;
 use crate::util::types::Number;
 
+// How many macro calls can be in progress at once, in each thread. Both backends stop at the same depth
+pub const MAX_CALL_DEPTH: usize = 4000;
+// Stack size of the main interpreter thread. Each call nests a few Rust frames in the tree walker, so this
+// fits `MAX_CALL_DEPTH` of them for most macros; `Runner::call` stops with an error before it would run out
+pub const STACK_SIZE: usize = 128 * 1024 * 1024;
+// Stack size of threads started by `spawn`, which can be many. The VM doesn't recurse on calls at all,
+// and the tree walker stops with a recursion error when the stack runs low, before `MAX_CALL_DEPTH` if it must
+pub const SPAWN_STACK_SIZE: usize = 16 * 1024 * 1024;
+// Stack the tree walker keeps free below its deepest call, for the frames that evaluating one call nests
+const STACK_MARGIN: usize = 1024 * 1024;
+
+// Whether anything was printed since `take_printed` last looked, by any thread
+static PRINTED: AtomicBool = AtomicBool::new(false);
+
+// What a statement asks of the code around it once it has run
+#[derive(Debug, Clone, PartialEq)]
+pub enum Flow {
+    Next,
+    // Optional label of the loop being targeted
+    Break(Option<String>),
+    Continue(Option<String>),
+    Return(Option<EvalType>),
+}
+
 pub struct Runner {
     pub stack: HashMap<i64, Number>,
     pub thought: Number,
-    pub macros: HashMap<String, Box<Vec<Node>>>,
-    pub expr: usize,
-    thread_handles: Arc<Mutex<Vec<thread::JoinHandle<()>>>>,
+    // Bodies are shared, so calls and spawned threads never copy them
+    pub macros: HashMap<String, Arc<Macro>>,
+    // Arguments of the macro calls in progress, innermost last
+    pub frames: Vec<Vec<Number>>,
+    // Arguments given to the script on the command line
+    pub args: Arc<Vec<Number>>,
+    pub arith: Arith,
+    // Span of the node currently being executed, used to locate runtime errors
+    pub span: Span,
+    thread_handles: ThreadHandles,
+    // Lowest stack address a call may start from, so deep recursion is an error instead of a stack overflow
+    stack_limit: usize,
 }
 impl Runner {
     pub fn new() -> Self {
@@ -18,128 +57,233 @@ impl Runner {
             stack: HashMap::new(),
             thought: Number::Int(0),
             macros: HashMap::new(),
-            expr: 0,
+            frames: Vec::new(),
+            args: Arc::new(Vec::new()),
+            arith: Arith::default(),
+            span: Span::default(),
             thread_handles: Arc::new(Mutex::new(Vec::new())),
+            stack_limit: 0,
         }
     }
-    pub fn run(&mut self, nodes: Vec<Node>, macros: Option<HashMap<String, Box<Vec<Node>>>>) -> Result<(), String> {
-        self.macros = macros.unwrap_or(HashMap::new());
-        for node in nodes {
+    // Lets calls use the stack from here on, of a thread whose stack is `size` bytes
+    pub fn limit_stack(&mut self, size: usize) {
+        self.stack_limit = stack_position().saturating_sub(size - STACK_MARGIN);
+    }
+    // Runs a program on the current state. Macros are added to the ones already known,
+    // so a REPL can keep one `Runner` across entries
+    pub fn run(&mut self, nodes: Vec<Node>, macros: HashMap<String, Macro>) -> NaquaResult<()> {
+        self.macros.extend(macros.into_iter().map(|(name, m)| (name, Arc::new(m))));
+        for node in &nodes {
             self.exec(node)?;
         }
 
-        self.wait_for_threads()?;
+        wait_for_threads(&self.thread_handles, |e| self.err(e))?;
         Ok(())
     }
-    fn exec(&mut self, node: Node) -> Result<bool, String> {
-        match node {
-            Node::Print(d) => {
-                print!("{}", self.eval(d.deref())?);
-                return Ok(false);
-            }
-            Node::Think(d) => {
-                self.thought = self.eval(d.deref())?.to_num()
-                    .map_err(|_| self.err("Unable to evaluate!"))?;
-                return Ok(false);
+    fn exec(&mut self, node: &Node) -> NaquaResult<Flow> {
+        self.span = node.span;
+        match &node.kind {
+            NodeKind::Print(d) => match &d.kind {
+                NodeKind::Str(text) => print(text),
+                _ => print(self.eval(d)?),
+            },
+            NodeKind::Think(d) => {
+                self.thought = self.eval(d)?.to_num();
             }
-            Node::Assign(i, val) => {
-                let index = match i.to_num()? {
-                    Number::Int(n) => n,
-                    Number::Float(n) => n.floor() as i64,
-                    Number::Thought => self.thought.float().floor() as i64,
-                };
-
-                let value = match val.deref() {
-                    Node::Out(n) => self.stack_get(n.to_num()?),
-                    Node::Literal(n) => match *n {
-                        Number::Int(m) => Number::Int(m),
-                        Number::Float(m) => Number::Float(m),
-                        Number::Thought => self.thought
-                    },
-                    Node::Eval(l, o, r) => self.eval(&Node::Eval(l.clone(), o.clone(), r.clone()))?.to_num().map_err(|()| String::new())?,
-                    _ => return Err(self.err("Unable to assign a non-data type to a stack index!"))
-                };
+            NodeKind::Assign(i, val) => {
+                let index = self.eval(i)?.to_index();
+                if let NodeKind::Str(text) = &val.kind {
+                    store_text(&mut self.stack, index, text);
+                    return Ok(Flow::Next);
+                }
+                let value = self.eval(val)?.to_num();
                 self.stack.insert(index, value);
-                return Ok(false);
             }
-            Node::Run(s) => {
-                let m = self.macros.get(&s).cloned().ok_or(self.err("Macro '{}' not found!"))?;
-                for node in m.deref() {
-                    if self.exec(node.clone())? {
-                        return Ok(true);
-                    }
-                }
-                return Ok(false);
+            NodeKind::Run(s, args) => {
+                // `break` and `continue` never reach past a macro body, so the call ends here
+                self.call(s, args)?;
             }
-            Node::Spawn(s) => {
-                let m = self.macros.get(&s).cloned().ok_or(self.err(&format!("Macro '{}' not found!", s)))?.clone();
-                let thread_handles = Arc::clone(&self.thread_handles);
+            NodeKind::Spawn(s, args) => {
+                let (m, frame) = self.prepare_call(s, args)?;
+                let macros = self.macros.clone();
+                let args = Arc::clone(&self.args);
+                let arith = self.arith;
+                let handles = Arc::clone(&self.thread_handles);
                 let handle = thread::Builder::new()
                     .name(s.clone())
+                    .stack_size(SPAWN_STACK_SIZE)
                     .spawn(move || {
                         let mut rt = Runner::new();
-                        for node in m.deref() {
-                            if let Err(e) = rt.exec(node.clone()) {
-                                eprintln!("Error in spawned thread '{}': {}", s, e);
-                                break;
-                            }
-                        }
+                        rt.limit_stack(SPAWN_STACK_SIZE);
+                        rt.macros = macros;
+                        rt.args = args;
+                        rt.arith = arith;
+                        // Threads spawned from here are waited for along with this one
+                        rt.thread_handles = handles;
+                        rt.frames.push(frame);
+                        rt.exec_block(&m.body).map(|_| ())
                     })
-                    .map_err(|e| self.err(&format!("Failed to spawn thread: {}", e)))?;
+                    .map_err(|e| self.err(RuntimeError::SpawnFailed(e.to_string())))?;
 
-                thread_handles.lock().unwrap().push(handle);
-                return Ok(false)
+                self.thread_handles.lock().unwrap().push(handle);
             }
-            Node::If(cond, exec) => {
-                if cond.to_num()? == self.thought {
-                    for node in exec.deref() {
-                        if self.exec(node.clone())? {
-                            return Ok(true);
-                        }
-                    }
-                }
-                return Ok(false);
+            NodeKind::If(cond, exec, otherwise) => {
+                let branch = if self.eval(cond)?.is_truthy() { exec } else { otherwise };
+                return self.exec_block(branch);
             }
-            Node::Loop(exec) => {
-                let mut broken = false;
-                loop {
-                    for node in exec.deref() {
-                        if self.exec(node.clone())? {
-                            broken = true;
-                            break;
-                        }
-                    }
-                    if broken { break; }
-                }
-                return Ok(false);
+            NodeKind::Loop { label, kind, body } => {
+                return self.exec_loop(label, kind, body);
+            }
+            NodeKind::Break(label) => {
+                return Ok(Flow::Break(label.clone()));
+            }
+            NodeKind::Continue(label) => {
+                return Ok(Flow::Continue(label.clone()));
             }
-            Node::Break => {
-                return Ok(true)
+            NodeKind::Exit(status) => {
+                let status = self.eval(status)?;
+                self.span = node.span;
+                exit(status.to_exit_code().map_err(|e| self.err(e))?);
+            }
+            NodeKind::Return(value) => {
+                let value = match value {
+                    Some(v) => Some(self.eval(v)?),
+                    None => None
+                };
+                return Ok(Flow::Return(value));
             }
             _ => {}
         }
-        Ok(false)
+        Ok(Flow::Next)
+    }
+    // Runs a macro in a new frame holding its arguments
+    pub fn call(&mut self, name: &str, args: &[Node]) -> NaquaResult<Flow> {
+        if self.frames.len() >= MAX_CALL_DEPTH || stack_position() < self.stack_limit {
+            return Err(self.err(RuntimeError::RecursionTooDeep(name.to_string())));
+        }
+        let (m, frame) = self.prepare_call(name, args)?;
+        self.frames.push(frame);
+        let flow = self.exec_block(&m.body);
+        self.frames.pop();
+        flow
     }
-    fn wait_for_threads(&self) -> Result<(), String> {
-        let mut handles = Vec::new();
-        {
-            let mut lock = self.thread_handles.lock().unwrap();
-            handles.append(&mut *lock);
+    // Looks up a macro and evaluates the arguments passed to it
+    fn prepare_call(&mut self, name: &str, args: &[Node]) -> NaquaResult<(Arc<Macro>, Vec<Number>)> {
+        let m = self.macros.get(name).map(Arc::clone).ok_or_else(|| self.err(RuntimeError::MacroNotFound(name.to_string())))?;
+        if m.params.len() != args.len() {
+            return Err(self.err(RuntimeError::ArgumentCount { name: name.to_string(), expected: m.params.len(), found: args.len() }));
+        }
+        let mut frame = Vec::with_capacity(args.len());
+        for arg in args {
+            frame.push(self.eval(arg)?.to_num());
         }
-        for handle in handles {
-            if let Err(_) = handle.join() {
-                return Err("A thread panicked".to_string());
+        Ok((m, frame))
+    }
+    // Runs each node of a body in order, stopping at the first `break`, `continue` or `return`
+    fn exec_block(&mut self, body: &[Node]) -> NaquaResult<Flow> {
+        for node in body {
+            match self.exec(node)? {
+                Flow::Next => {}
+                flow => return Ok(flow)
             }
         }
-        Ok(())
+        Ok(Flow::Next)
     }
-    fn stack_get(&self, i: Number) -> Number {
-        let index = if i.int().is_none() {
-            i.float().floor() as i64
-        } else {
-            i.int().unwrap()
-        };
+    fn exec_loop(&mut self, label: &Option<String>, kind: &LoopKind, body: &[Node]) -> NaquaResult<Flow> {
+        match kind {
+            LoopKind::Forever => loop {
+                if let Some(flow) = self.iteration(label, body)? { return Ok(flow); }
+            },
+            LoopKind::While(cond) => {
+                while self.eval(cond)?.is_truthy() {
+                    if let Some(flow) = self.iteration(label, body)? { return Ok(flow); }
+                }
+            }
+            LoopKind::Repeat(count) => {
+                let count = self.eval(count)?.to_index();
+                for _ in 0..count {
+                    if let Some(flow) = self.iteration(label, body)? { return Ok(flow); }
+                }
+            }
+            LoopKind::For(cell, first, last) => {
+                let cell = self.eval(cell)?.to_index();
+                let first = self.eval(first)?.to_index();
+                let last = self.eval(last)?.to_index();
+                // Counts down when `first` is above `last`
+                let step = if first <= last { 1 } else { -1 };
+                let mut i = first;
+                loop {
+                    self.stack.insert(cell, Number::Int(i));
+                    if let Some(flow) = self.iteration(label, body)? { return Ok(flow); }
+                    if i == last { break; }
+                    i += step;
+                }
+            }
+        }
+        Ok(Flow::Next)
+    }
+    // Runs one pass of a loop body. Returns `None` to keep looping, or the flow the loop should end with:
+    // `Next` when this loop was broken out of, the original flow when it targets an outer loop
+    fn iteration(&mut self, label: &Option<String>, body: &[Node]) -> NaquaResult<Option<Flow>> {
+        let targets_this = |target: &Option<String>| target.is_none() || target == label;
+        Ok(match self.exec_block(body)? {
+            Flow::Next => None,
+            Flow::Continue(target) if targets_this(&target) => None,
+            Flow::Break(target) if targets_this(&target) => Some(Flow::Next),
+            flow => Some(flow)
+        })
+    }
+}
 
-        self.stack.get(&index).or(Some(&Number::Int(0))).unwrap().clone()
+// Threads spawned by a program, shared with the threads themselves so they can add their own
+pub type ThreadHandles = Arc<Mutex<Vec<thread::JoinHandle<NaquaResult<()>>>>>;
+
+// Joins every spawned thread, including ones spawned while waiting. The first thread that failed fails the program;
+// a panicked one is reported through `err`, which places the error
+pub fn wait_for_threads(handles: &ThreadHandles, err: impl Fn(RuntimeError) -> NaquaError) -> NaquaResult<()> {
+    loop {
+        let joining = std::mem::take(&mut *handles.lock().unwrap());
+        if joining.is_empty() {
+            return Ok(());
+        }
+        for handle in joining {
+            let name = handle.thread().name().unwrap_or_default().to_string();
+            match handle.join() {
+                Ok(result) => result?,
+                Err(_) => return Err(err(RuntimeError::ThreadPanicked(name))),
+            }
+        }
     }
-}
\ No newline at end of file
+}
+
+// Stores the characters of `text` from `index` on, followed by a 0 that marks its end
+pub fn store_text(stack: &mut HashMap<i64, Number>, index: i64, text: &str) {
+    let end = text.chars().fold(index, |i, c| {
+        stack.insert(i, Number::Char(c));
+        i + 1
+    });
+    stack.insert(end, Number::Int(0));
+}
+
+// Roughly where the stack currently ends: the address of a local. Stacks grow down on every platform this runs on
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+// Writes `value` to stdout as `print` shows it
+pub fn print(value: impl Display) {
+    print!("{value}");
+    PRINTED.store(true, Ordering::Relaxed);
+}
+
+// Whether anything was printed since the last call, so the REPL knows if an entry's output needs a new line after it
+pub fn take_printed() -> bool {
+    PRINTED.swap(false, Ordering::Relaxed)
+}
+
+// Ends the process with `status` as its exit code once everything printed so far is out; spawned threads end with it
+pub fn exit(status: i32) -> ! {
+    io::stdout().flush().ok();
+    std::process::exit(status)
+}
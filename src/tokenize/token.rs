@@ -1,13 +1,52 @@
+use std::fmt::{Display, Formatter};
+use crate::util::span::Span;
 use crate::util::types::*;
 
 #[derive(Debug, PartialEq, Clone)]
-pub enum Token {
+pub enum TokenKind {
     OpenBrace,
     CloseBrace,
+    OpenParen,
+    CloseParen,
+    Colon,
+    Comma,
     Data(Number),
+    Str(String),
     Keyword(Keyword),
     OpToken(Operator),
     RTKeyword(String), // Run-time keyword, keyword evaluated at runtime (for macros)
     NewLine
 }
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::OpenBrace => write!(f, "'{{'"),
+            TokenKind::CloseBrace => write!(f, "'}}'"),
+            TokenKind::OpenParen => write!(f, "'('"),
+            TokenKind::CloseParen => write!(f, "')'"),
+            TokenKind::Colon => write!(f, "':'"),
+            TokenKind::Comma => write!(f, "','"),
+            TokenKind::Data(Number::Int(n)) => write!(f, "number '{n}'"),
+            TokenKind::Data(Number::Float(n)) => write!(f, "number '{n}'"),
+            TokenKind::Data(Number::Char(c)) => write!(f, "character {c:?}"),
+            TokenKind::Data(Number::Big(n)) => write!(f, "number '{n}'"),
+            TokenKind::Data(Number::Thought) => write!(f, "'thought'"),
+            TokenKind::Str(s) => write!(f, "string {s:?}"),
+            TokenKind::Keyword(k) => write!(f, "keyword '{}'", k.to_str()),
+            TokenKind::OpToken(o) => write!(f, "operator '{o}'"),
+            TokenKind::RTKeyword(s) => write!(f, "name '{s}'"),
+            TokenKind::NewLine => write!(f, "end of line"),
+        }
+    }
+}
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+impl Token {
+    pub fn new(kind: TokenKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
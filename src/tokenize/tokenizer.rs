@@ -1,106 +1,318 @@
-use crate::tokenize::token::Token;
-use crate::tokenize::token::Token::{OpToken, RTKeyword};
+use std::sync::Arc;
+use num_bigint::BigInt;
+use crate::tokenize::token::{Token, TokenKind};
+use crate::tokenize::token::TokenKind::{OpToken, RTKeyword};
+use crate::util::err::{NaquaError, NaquaResult, TokenizeError};
 use crate::util::Is;
+use crate::util::span::Span;
 use crate::util::types::{Keyword, Number, Operator};
 
 pub struct Tokenizer {
     pos: usize,
-    row: usize,
+    line: usize,
+    col: usize,
     input: String,
+    chars: Vec<(usize, char)>,
+    file: usize,
 }
 impl Tokenizer {
-    pub fn new(input: String) -> Self {
-        Self { pos: 0, row: 0, input }
+    // `file` is the index of the source among the loaded files, and ends up in every span produced
+    pub fn new(input: String, file: usize) -> Self {
+        let chars = input.char_indices().collect();
+        Self { pos: 0, line: 1, col: 1, input, chars, file }
     }
-    pub fn tokenize(&mut self) -> Result<Vec<Token>, String> {
+    pub fn tokenize(&mut self) -> NaquaResult<Vec<Token>> {
         let mut tokens = Vec::new();
-        let chars: Vec<char> = self.input.chars().collect();
 
-        while self.pos < chars.len() {
-            let c = chars[self.pos];
+        while self.pos < self.chars.len() {
+            let c = self.chars[self.pos].1;
+            let start = self.mark();
 
             if c == '#' {
-                while self.pos < chars.len() && chars[self.pos] != '\n' {
+                while self.pos < self.chars.len() && self.chars[self.pos].1 != '\n' {
                     self.go();
                 }
-                self.go();
                 continue;
             }
             if Is::whitespace(c) {
+                self.go();
                 if c == '\n' {
-                    self.row += 1;
-                    tokens.push(Token::NewLine);
+                    tokens.push(Token::new(TokenKind::NewLine, self.span_from(start)));
                 }
-                self.go();
                 continue;
             }
             if Is::letter(c) || "_".contains(c) {
-                let start_pos = self.pos;
-                while self.pos < chars.len() && Is::rtk_compatible(chars[self.pos]) {
-                    self.go();
+                loop {
+                    match self.chars.get(self.pos) {
+                        // A '-' only joins a name when a letter follows and the word so far isn't a keyword,
+                        // so `my-macro` is one name while `thought-1` and `n-1` are subtractions
+                        Some((_, '-')) if !self.hyphen_in_name(start) => break,
+                        Some(&(_, ch)) if Is::rtk_compatible(ch) => self.go(),
+                        // `module::name` refers to a macro from an included file
+                        Some((_, ':')) if self.path_separator() => {
+                            self.go();
+                            self.go();
+                        }
+                        _ => break
+                    }
                 }
-                let word = String::from(&self.input[start_pos..self.pos]);
-                match Keyword::from(word.as_str()) {
-                    Some(k) => {
-                        if k == Keyword::Thought { tokens.push(Token::Data(Number::Thought)) }
-                        else { tokens.push(Token::Keyword(k.clone())) };
+                let span = self.span_from(start);
+                let word = String::from(&self.input[span.start..span.end]);
+                match (Keyword::from(word.as_str()), Operator::from(word.as_str())) {
+                    (Some(k), _) => {
+                        if k == Keyword::Thought { tokens.push(Token::new(TokenKind::Data(Number::Thought), span)) }
+                        else { tokens.push(Token::new(TokenKind::Keyword(k), span)) };
                     }
-                    None => {
-                        tokens.push(RTKeyword(word));
+                    (None, Some(o)) => tokens.push(Token::new(OpToken(o), span)),
+                    (None, None) => {
+                        tokens.push(Token::new(RTKeyword(word), span));
                     }
                 }
                 continue;
             }
-            if Is::digit(c) || c == '-' {
-                let start_pos = self.pos;
-                let mut has_decimal = false;
+            if c == '"' {
                 self.go();
-
-                while self.pos < chars.len() {
-                    let ch = chars[self.pos];
-                    if Is::digit(ch) {
-                        self.go();
-                    } else if ch == '.' && !has_decimal {
-                        has_decimal = true;
-                        self.go();
-                    } else {
-                        break;
+                let mut text = String::new();
+                loop {
+                    match self.chars.get(self.pos) {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => {
+                            let c = self.escape()?;
+                            text.push(c);
+                        }
+                        Some(&(_, ch)) if ch != '\n' => {
+                            text.push(ch);
+                            self.go();
+                        }
+                        _ => return Err(NaquaError::Tokenize(TokenizeError::UnterminatedString, self.span_from(start)))
                     }
                 }
-
-                let number_str = &self.input[start_pos..self.pos];
-
-                if c == '-' && number_str.len() == 1 {
-                    tokens.push(OpToken(Operator::Sub));
-                    continue
-                }
-
-                if has_decimal {
-                    tokens.push(Token::Data(Number::Float(number_str.parse().map_err(|_| format!("Incorrect float formatting '{number_str}' at line {}", self.row))?)))
-                } else {
-                    tokens.push(Token::Data(Number::Int(number_str.parse().map_err(|_| format!("Incorrect integer formatting '{number_str}' at line {}", self.row))?)))
-                }
+                self.go();
+                tokens.push(Token::new(TokenKind::Str(text), self.span_from(start)));
+                continue;
+            }
+            if c == '\'' {
+                let c = self.char_literal()?;
+                tokens.push(Token::new(TokenKind::Data(Number::Char(c)), self.span_from(start)));
+                continue;
+            }
+            // '-' is always an operator token; the parser decides whether it negates or subtracts
+            if Is::digit(c) || (c == '.' && matches!(self.chars.get(self.pos + 1), Some(&(_, d)) if Is::digit(d))) {
+                let n = self.number()?;
+                tokens.push(Token::new(TokenKind::Data(n), self.span_from(start)));
                 continue;
             }
             if Is::brace(c) {
+                self.go();
                 match c {
-                    '{' => tokens.push(Token::OpenBrace),
-                    '}' => tokens.push(Token::CloseBrace),
+                    '{' => tokens.push(Token::new(TokenKind::OpenBrace, self.span_from(start))),
+                    '}' => tokens.push(Token::new(TokenKind::CloseBrace, self.span_from(start))),
                     _ => {}
                 }
+                continue;
+            }
+            if Is::paren(c) {
                 self.go();
+                match c {
+                    '(' => tokens.push(Token::new(TokenKind::OpenParen, self.span_from(start))),
+                    ')' => tokens.push(Token::new(TokenKind::CloseParen, self.span_from(start))),
+                    _ => {}
+                }
+                continue;
+            }
+            if c == ':' || c == ',' {
+                self.go();
+                let kind = if c == ':' { TokenKind::Colon } else { TokenKind::Comma };
+                tokens.push(Token::new(kind, self.span_from(start)));
                 continue;
             }
             if Is::operator(c) {
-                tokens.push(OpToken(Operator::from(c)));
                 self.go();
+                // `//` is one operator
+                if c == '/' && matches!(self.chars.get(self.pos), Some((_, '/'))) {
+                    self.go();
+                }
+                let span = self.span_from(start);
+                tokens.push(Token::new(OpToken(Operator::from(&self.input[span.start..span.end]).unwrap()), span));
+                continue;
+            }
+            if Is::comparison(c) {
+                self.go();
+                if let Some((_, '=')) = self.chars.get(self.pos) {
+                    self.go();
+                }
+                let span = self.span_from(start);
+                match Operator::from(&self.input[span.start..span.end]) {
+                    Some(o) => tokens.push(Token::new(OpToken(o), span)),
+                    None => return Err(NaquaError::Tokenize(TokenizeError::UnexpectedChar(c), span))
+                }
                 continue;
             }
+            self.go();
+            return Err(NaquaError::Tokenize(TokenizeError::UnexpectedChar(c), self.span_from(start)));
         }
 
         Ok(tokens)
     }
     fn go(&mut self) {
+        if let Some((_, '\n')) = self.chars.get(self.pos) {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
         self.pos += 1;
     }
-}
\ No newline at end of file
+    // Reads a number literal: decimal with an optional fraction and exponent (`1.5e-3`, `.5`),
+    // or a `0x`, `0b` or `0o` integer. `_` can separate digits anywhere after the first
+    fn number(&mut self) -> NaquaResult<Number> {
+        let start = self.mark();
+        let radix = match (self.chars[self.pos].1, self.chars.get(self.pos + 1).map(|&(_, c)| c)) {
+            ('0', Some('x' | 'X')) => 16,
+            ('0', Some('b' | 'B')) => 2,
+            ('0', Some('o' | 'O')) => 8,
+            _ => 10
+        };
+        if radix != 10 {
+            self.go();
+            self.go();
+            // Letters are taken too, so `0b102` or `0xfg` is one bad literal rather than a number and a name
+            while let Some(&(_, ch)) = self.chars.get(self.pos) {
+                if !ch.is_ascii_alphanumeric() && ch != '_' { break; }
+                self.go();
+            }
+            let span = self.span_from(start);
+            let text = &self.input[span.start..span.end];
+            let digits = text[2..].replace('_', "");
+            if digits.is_empty() || !digits.chars().all(|d| d.is_digit(radix)) {
+                return Err(NaquaError::Tokenize(TokenizeError::InvalidInteger(text.to_string()), span));
+            }
+            return i64::from_str_radix(&digits, radix).map(Number::Int)
+                .map_err(|_| NaquaError::Tokenize(TokenizeError::NumberOutOfRange(text.to_string()), span));
+        }
+
+        let first = self.pos;
+        let mut is_float = false;
+        let mut has_exponent = false;
+        while let Some(&(_, ch)) = self.chars.get(self.pos) {
+            let next = |n: usize| self.chars.get(self.pos + n).map(|&(_, c)| c);
+            if Is::digit(ch) || (ch == '_' && self.pos > first) {
+                self.go();
+            } else if ch == '.' && !is_float {
+                is_float = true;
+                self.go();
+            } else if matches!(ch, 'e' | 'E') && !has_exponent
+                && (next(1).is_some_and(Is::digit) || (matches!(next(1), Some('+' | '-')) && next(2).is_some_and(Is::digit))) {
+                is_float = true;
+                has_exponent = true;
+                self.go();
+                self.go();
+            } else {
+                break;
+            }
+        }
+        let span = self.span_from(start);
+        let text = &self.input[span.start..span.end];
+        let digits = text.replace('_', "");
+        if is_float {
+            match digits.parse::<f64>() {
+                Ok(f) if f.is_finite() => Ok(Number::Float(f)),
+                Ok(_) => Err(NaquaError::Tokenize(TokenizeError::NumberOutOfRange(text.to_string()), span)),
+                Err(_) => Err(NaquaError::Tokenize(TokenizeError::InvalidFloat(text.to_string()), span))
+            }
+        } else if digits == "9223372036854775808" {
+            // Only fits once negated, which the parser checks for: `-9223372036854775808` is the smallest integer
+            Ok(Number::Big(Arc::new(BigInt::from(1u64 << 63))))
+        } else {
+            digits.parse().map(Number::Int)
+                .map_err(|_| NaquaError::Tokenize(TokenizeError::NumberOutOfRange(text.to_string()), span))
+        }
+    }
+    // Reads a character literal like `'A'` or `'\n'`
+    fn char_literal(&mut self) -> NaquaResult<char> {
+        let start = self.mark();
+        self.go();
+        let c = match self.chars.get(self.pos) {
+            Some((_, '\\')) => Some(self.escape()?),
+            Some(&(_, ch)) if ch != '\'' && ch != '\n' => {
+                self.go();
+                Some(ch)
+            }
+            _ => None
+        };
+        if let (Some(c), Some((_, '\''))) = (c, self.chars.get(self.pos)) {
+            self.go();
+            return Ok(c);
+        }
+        // Take the rest of a too-long literal, so the error shows all of it
+        while let Some(&(_, ch)) = self.chars.get(self.pos) {
+            if ch == '\n' { break; }
+            self.go();
+            if ch == '\'' { break; }
+        }
+        let span = self.span_from(start);
+        Err(NaquaError::Tokenize(TokenizeError::InvalidCharLiteral(self.input[span.start..span.end].to_string()), span))
+    }
+    // Reads an escape sequence starting at the current '\': `\n`, `\t`, `\r`, `\0`, `\"`, `\'`, `\\` or `\u{<hex>}`
+    fn escape(&mut self) -> NaquaResult<char> {
+        let start = self.mark();
+        self.go();
+        let c = match self.chars.get(self.pos) {
+            Some(&(_, c)) if c != '\n' => c,
+            _ => return Err(NaquaError::Tokenize(TokenizeError::UnterminatedString, self.span_from(start)))
+        };
+        self.go();
+        let escaped = match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '"' => '"',
+            '\'' => '\'',
+            '\\' => '\\',
+            'u' => {
+                let mut hex = String::new();
+                let closed = if let Some((_, '{')) = self.chars.get(self.pos) {
+                    self.go();
+                    while let Some(&(_, h)) = self.chars.get(self.pos) {
+                        if h == '}' || h == '"' || h == '\n' { break; }
+                        hex.push(h);
+                        self.go();
+                    }
+                    let closed = matches!(self.chars.get(self.pos), Some((_, '}')));
+                    if closed { self.go(); }
+                    closed
+                } else {
+                    false
+                };
+                let code = if closed && (1..=6).contains(&hex.len()) { u32::from_str_radix(&hex, 16).ok() } else { None };
+                return code.and_then(char::from_u32)
+                    .ok_or_else(|| NaquaError::Tokenize(TokenizeError::InvalidUnicodeEscape(hex), self.span_from(start)));
+            }
+            other => return Err(NaquaError::Tokenize(TokenizeError::InvalidEscape(other), self.span_from(start)))
+        };
+        Ok(escaped)
+    }
+    // Does the current '-' continue the word that began at `start`?
+    fn hyphen_in_name(&self, start: Span) -> bool {
+        let word = &self.input[start.start..self.offset()];
+        Keyword::from(word).is_none() && Operator::from(word).is_none()
+            && matches!(self.chars.get(self.pos + 1), Some(&(_, c)) if Is::letter(c) || c == '_')
+    }
+    // Is the current ':' the start of a `::` followed by a name?
+    fn path_separator(&self) -> bool {
+        matches!(self.chars.get(self.pos + 1), Some((_, ':')))
+            && matches!(self.chars.get(self.pos + 2), Some(&(_, c)) if Is::letter(c) || c == '_')
+    }
+    // Byte offset of the current character
+    fn offset(&self) -> usize {
+        self.chars.get(self.pos).map_or(self.input.len(), |(i, _)| *i)
+    }
+    fn mark(&self) -> Span {
+        let offset = self.offset();
+        Span { file: self.file, ..Span::new(offset, offset, self.line, self.col) }
+    }
+    fn span_from(&self, start: Span) -> Span {
+        Span { end: self.offset(), ..start }
+    }
+}
@@ -0,0 +1,68 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use colored::{control, Color, Colorize};
+use crate::util::err::{NaquaError, NaquaWarning};
+use crate::util::span::Span;
+
+// Renders an error rustc-style: header, file location, the offending source line and a caret underline
+pub fn render(err: &NaquaError, file: &str, source: &str) -> String {
+    let stage = match err {
+        NaquaError::Tokenize(..) => "tokenizing error",
+        NaquaError::Parse(..) => "parsing error",
+        NaquaError::Runtime(..) => "runtime error",
+    };
+    let header = format!("{stage}[E{}]", err.code());
+    for_stderr(|| snippet(Color::Red, &header, &err.message(), err.span(), err.hint(), file, source))
+}
+
+// Same layout as `render`, in yellow and with a W-prefixed code
+pub fn render_warning(warning: &NaquaWarning, file: &str, source: &str) -> String {
+    let header = format!("warning[W{:03}]", warning.code());
+    for_stderr(|| snippet(Color::Yellow, &header, &warning.to_string(), warning.span(), warning.hint(), file, source))
+}
+
+// Diagnostics are written to stderr, but `colored` decides by whether stdout is a terminal, so the decision is
+// made here for the duration of `render`, honoring CLICOLOR_FORCE, NO_COLOR and CLICOLOR like `colored` does
+fn for_stderr(render: impl FnOnce() -> String) -> String {
+    let forced = env::var("CLICOLOR_FORCE").is_ok_and(|v| v != "0");
+    let disabled = env::var("NO_COLOR").is_ok() || env::var("CLICOLOR").is_ok_and(|v| v == "0");
+    control::set_override(forced || (!disabled && io::stderr().is_terminal()));
+    let out = render();
+    control::unset_override();
+    out
+}
+
+fn snippet(color: Color, header: &str, message: &str, span: Span, hint: Option<String>, file: &str, source: &str) -> String {
+    let mut out = format!("{}{} {}\n", header.color(color).bold(), ":".bold(), message.bold());
+
+    let line_no = span.line.to_string();
+    let gutter = " ".repeat(line_no.len());
+    out += &format!("{gutter}{} {file}:{}:{}\n", "-->".blue().bold(), span.line, span.col);
+
+    if let Some(line) = source.lines().nth(span.line.saturating_sub(1)) {
+        let line_start = source[..span.start.min(source.len())]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let line_end = line_start + line.len();
+        let from = span.start.min(line_end);
+        let to = span.end.min(line_end).max(from);
+        let underline = source.get(from..to).map_or(0, |s| s.chars().count()).max(1);
+        // Keep tabs so the carets line up with the source line
+        let padding: String = line.chars()
+            .take(span.col.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        let bar = "|".blue().bold();
+        out += &format!("{gutter} {bar}\n");
+        out += &format!("{} {bar} {}\n", line_no.blue().bold(), line.trim_end());
+        out += &format!(
+            "{gutter} {bar} {padding}{}\n",
+            "^".repeat(underline).color(color).bold()
+        );
+    }
+    if let Some(hint) = hint {
+        out += &format!("{gutter} {} {}: {hint}\n", "=".blue().bold(), "hint".bold());
+    }
+    out
+}
@@ -1,17 +1,367 @@
-use std::string::ParseError;
+use std::fmt::{Display, Formatter};
 use crate::parse::parser::Parser;
-use crate::runtime::runtime::Runner;
+use crate::runtime::runtime::{Runner, MAX_CALL_DEPTH};
+use crate::tokenize::token::TokenKind;
+use crate::util::span::Span;
 
-pub fn err_code(code: i16) -> String {
-    format!("Error code {code}")
+pub type NaquaResult<T> = Result<T, NaquaError>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NaquaError {
+    Tokenize(TokenizeError, Span),
+    Parse(ParseError, Span),
+    Runtime(RuntimeError, Span),
+}
+impl NaquaError {
+    pub fn span(&self) -> Span {
+        match self {
+            NaquaError::Tokenize(_, s) | NaquaError::Parse(_, s) | NaquaError::Runtime(_, s) => *s
+        }
+    }
+    // Stable numeric code: 1xx for tokenizing, 2xx for parsing, 3xx for runtime errors
+    pub fn code(&self) -> u16 {
+        match self {
+            NaquaError::Tokenize(e, _) => e.code(),
+            NaquaError::Parse(e, _) => e.code(),
+            NaquaError::Runtime(e, _) => e.code(),
+        }
+    }
+    // Status the process ends with: 3 when tokenizing fails, 4 when parsing fails and 1 at runtime.
+    // 2 is used for command-line mistakes
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            NaquaError::Tokenize(..) => 3,
+            NaquaError::Parse(..) => 4,
+            NaquaError::Runtime(..) => 1,
+        }
+    }
+    // Short suggestion shown under a diagnostic
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            NaquaError::Tokenize(e, _) => e.hint(),
+            NaquaError::Parse(e, _) => e.hint(),
+            NaquaError::Runtime(e, _) => e.hint(),
+        }
+    }
+    pub fn message(&self) -> String {
+        match self {
+            NaquaError::Tokenize(e, _) => e.to_string(),
+            NaquaError::Parse(e, _) => e.to_string(),
+            NaquaError::Runtime(e, _) => e.to_string(),
+        }
+    }
+}
+impl Display for NaquaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[E{}] {}: Found at {}", self.code(), self.message(), self.span())
+    }
+}
+impl std::error::Error for NaquaError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenizeError {
+    InvalidInteger(String),
+    InvalidFloat(String),
+    UnexpectedChar(char),
+    UnterminatedString,
+    InvalidEscape(char),
+    // Digits found between the braces of `\u{...}`
+    InvalidUnicodeEscape(String),
+    NumberOutOfRange(String),
+    InvalidCharLiteral(String),
 }
+impl TokenizeError {
+    pub fn code(&self) -> u16 {
+        match self {
+            TokenizeError::InvalidInteger(_) => 101,
+            TokenizeError::InvalidFloat(_) => 102,
+            TokenizeError::UnexpectedChar(_) => 103,
+            TokenizeError::UnterminatedString => 104,
+            TokenizeError::InvalidEscape(_) => 105,
+            TokenizeError::InvalidUnicodeEscape(_) => 106,
+            TokenizeError::NumberOutOfRange(_) => 107,
+            TokenizeError::InvalidCharLiteral(_) => 108,
+        }
+    }
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            TokenizeError::InvalidInteger(_) | TokenizeError::InvalidFloat(_) => Some("numbers look like 42, 1_000, 1.5, .5, 1e3, 0xff, 0b1010 or 0o17".to_string()),
+            TokenizeError::UnexpectedChar('=') => Some("compare with '==', or assign with '<index> in <value>'".to_string()),
+            TokenizeError::UnexpectedChar(_) => Some("remove it, or start a comment with '#'".to_string()),
+            TokenizeError::UnterminatedString => Some("add the closing '\"' before the end of the line".to_string()),
+            TokenizeError::InvalidEscape(_) => Some("valid escapes are \\n, \\t, \\r, \\0, \\\", \\', \\\\ and \\u{...}; write '\\\\' for a backslash".to_string()),
+            TokenizeError::InvalidUnicodeEscape(_) => Some("write a code point as 1 to 6 hex digits, like \\u{1F600}".to_string()),
+            TokenizeError::NumberOutOfRange(_) => Some(format!("integers go from {} to {} and floats up to about 1.8e308; write larger integers as floats, like 1e20", i64::MIN, i64::MAX)),
+            TokenizeError::InvalidCharLiteral(_) => Some("put exactly one character between single quotes, like 'A' or '\\n'; use double quotes for text".to_string()),
+        }
+    }
+}
+impl Display for TokenizeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenizeError::InvalidInteger(s) => write!(f, "Invalid digits in integer '{s}'"),
+            TokenizeError::InvalidFloat(s) => write!(f, "Incorrect float formatting '{s}'"),
+            TokenizeError::UnexpectedChar(c) => write!(f, "Unexpected character '{c}'"),
+            TokenizeError::UnterminatedString => write!(f, "Unterminated string"),
+            TokenizeError::InvalidEscape(c) => write!(f, "Unknown escape sequence '\\{c}'"),
+            TokenizeError::InvalidUnicodeEscape(hex) => write!(f, "Invalid unicode escape '\\u{{{hex}}}'"),
+            TokenizeError::NumberOutOfRange(s) => write!(f, "Number '{s}' is out of range"),
+            TokenizeError::InvalidCharLiteral(s) => write!(f, "Invalid character literal {s}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    // `context` completes the sentence "Expected a value ..."
+    ExpectedValue { context: &'static str, found: Option<TokenKind> },
+    // `after` is the keyword that needs a macro name
+    ExpectedMacroName { after: &'static str, found: Option<TokenKind> },
+    ExpectedBlock { construct: &'static str, found: Option<TokenKind> },
+    UnclosedBlock(&'static str),
+    NestedDefine(&'static str),
+    UnexpectedToken(TokenKind),
+    MisplacedIn,
+    FloatIndex(f64),
+    LoneValue,
+    UnclosedParen,
+    ExpectedKeyword { keyword: &'static str, found: Option<TokenKind> },
+    UnknownLabel(String),
+    ContinueOutsideLoop,
+    MisplacedLabel(String),
+    UnknownName(String),
+    ReturnOutsideMacro,
+    ExpectedParamName(Option<TokenKind>),
+    DuplicateParam(String),
+    BreakOutsideLoop,
+    ExpectedPath(Option<TokenKind>),
+    MisplacedInclude(&'static str),
+    IncludeFailed { path: String, reason: String },
+    IncludeCycle(String),
+    StatementInInclude,
+    // `first` is where the macro was first defined, as `path:line:col`
+    DuplicateMacro { name: String, first: String },
+    UndefinedMacro(String),
+    // `first` is the path of the include that already uses the namespace
+    NamespaceClash { namespace: String, first: String },
+}
+impl ParseError {
+    pub fn code(&self) -> u16 {
+        match self {
+            ParseError::ExpectedValue { .. } => 201,
+            ParseError::ExpectedMacroName { .. } => 202,
+            ParseError::ExpectedBlock { .. } => 203,
+            ParseError::UnclosedBlock(_) => 204,
+            ParseError::NestedDefine(_) => 205,
+            ParseError::UnexpectedToken(_) => 206,
+            ParseError::MisplacedIn => 207,
+            ParseError::FloatIndex(_) => 208,
+            ParseError::LoneValue => 209,
+            ParseError::UnclosedParen => 210,
+            ParseError::ExpectedKeyword { .. } => 211,
+            ParseError::UnknownLabel(_) => 212,
+            ParseError::ContinueOutsideLoop => 213,
+            ParseError::MisplacedLabel(_) => 214,
+            ParseError::UnknownName(_) => 215,
+            ParseError::ReturnOutsideMacro => 216,
+            ParseError::ExpectedParamName(_) => 217,
+            ParseError::DuplicateParam(_) => 218,
+            ParseError::BreakOutsideLoop => 219,
+            ParseError::ExpectedPath(_) => 220,
+            ParseError::MisplacedInclude(_) => 221,
+            ParseError::IncludeFailed { .. } => 222,
+            ParseError::IncludeCycle(_) => 223,
+            ParseError::StatementInInclude => 224,
+            ParseError::DuplicateMacro { .. } => 225,
+            ParseError::UndefinedMacro(_) => 226,
+            ParseError::NamespaceClash { .. } => 227,
+        }
+    }
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            ParseError::ExpectedValue { .. } => Some("values are numbers, 'thought', 'out <index>' or '( ... )'".to_string()),
+            ParseError::ExpectedMacroName { after, .. } => Some(format!("write '{after} <name>' where the name is not a reserved keyword")),
+            ParseError::ExpectedBlock { .. } => Some("blocks are wrapped in '{ ... }'".to_string()),
+            ParseError::UnclosedBlock(_) => Some("add the missing '}'".to_string()),
+            ParseError::NestedDefine(_) => Some("move the 'define' to the top level of the file".to_string()),
+            ParseError::MisplacedIn => Some("assignments are written as '<index> in <value>'".to_string()),
+            ParseError::FloatIndex(n) => Some(format!("use a whole number such as '{}'", n.floor())),
+            ParseError::LoneValue => Some("use 'print' to output a value or 'think' to store it".to_string()),
+            ParseError::UnclosedParen => Some("add the missing ')'".to_string()),
+            ParseError::ExpectedKeyword { keyword: "from" | "to", .. } => Some("counted loops are written as 'for <cell> from <first> to <last> { ... }'".to_string()),
+            ParseError::ExpectedKeyword { .. } => None,
+            ParseError::UnknownLabel(label) => Some(format!("label an enclosing loop with '{label}: loop {{ ... }}'")),
+            ParseError::ContinueOutsideLoop => Some("'continue' can only be used inside a loop".to_string()),
+            ParseError::MisplacedLabel(_) => Some("labels are written right before a loop, as '<label>: loop { ... }'".to_string()),
+            ParseError::UnknownName(name) if name.contains('-') => Some(format!("names can contain '-'; to subtract, put spaces around it: '{}'", name.replacen('-', " - ", 1))),
+            ParseError::UnknownName(_) => Some("only parameters of the enclosing macro can be used as values, as in 'define m(n) { ... }'".to_string()),
+            ParseError::ReturnOutsideMacro => Some("'return' can only be used inside a macro definition".to_string()),
+            ParseError::ExpectedParamName(_) => Some("parameters are written as 'define <name>(a, b) { ... }'".to_string()),
+            ParseError::DuplicateParam(_) => Some("give each parameter a different name".to_string()),
+            ParseError::BreakOutsideLoop => Some("'break' only ends loops; use 'return' to leave a macro early".to_string()),
+            ParseError::ExpectedPath(_) => Some("write 'include \"<path>\"', relative to this file".to_string()),
+            ParseError::MisplacedInclude(_) => Some("move the 'include' to the top level of the file".to_string()),
+            ParseError::IncludeFailed { .. } => Some("paths are relative to the file containing the 'include'".to_string()),
+            ParseError::IncludeCycle(_) => Some("files cannot include each other in a loop".to_string()),
+            ParseError::StatementInInclude => Some("included files may only contain 'define' and 'include'".to_string()),
+            ParseError::DuplicateMacro { first, .. } => Some(format!("first defined at {first}; rename one of them")),
+            ParseError::UndefinedMacro(name) => Some(format!("define it with 'define {name} {{ ... }}', or include the file that does")),
+            ParseError::NamespaceClash { namespace, .. } => Some(format!("included macros are namespaced by their file name, so both files would define '{namespace}::' macros; rename one of the files")),
+            ParseError::UnexpectedToken(_) => None,
+        }
+    }
+}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::ExpectedValue { context, found } => write!(f, "Expected a value {context}, found {}", found_str(found)),
+            ParseError::ExpectedMacroName { after, found } => write!(f, "Expected a macro name after '{after}', found {}", found_str(found)),
+            ParseError::ExpectedBlock { construct, found } => write!(f, "Expected opening brace for {construct}, found {}", found_str(found)),
+            ParseError::UnclosedBlock(construct) => write!(f, "Unclosed block in {construct}"),
+            ParseError::NestedDefine(construct) => write!(f, "Macro definitions are not allowed in {construct}!"),
+            ParseError::UnexpectedToken(t) => write!(f, "Unexpected {t}"),
+            ParseError::MisplacedIn => write!(f, "'in' must follow a stack index!"),
+            ParseError::FloatIndex(n) => write!(f, "Floats are not valid indices, found '{n}'!"),
+            ParseError::LoneValue => write!(f, "A value on its own is not a statement!"),
+            ParseError::UnclosedParen => write!(f, "Unclosed parenthesis in expression"),
+            ParseError::ExpectedKeyword { keyword, found } => write!(f, "Expected '{keyword}', found {}", found_str(found)),
+            ParseError::UnknownLabel(label) => write!(f, "No enclosing loop is labeled '{label}'"),
+            ParseError::ContinueOutsideLoop => write!(f, "'continue' outside of a loop!"),
+            ParseError::MisplacedLabel(label) => write!(f, "Label '{label}' must be followed by a loop"),
+            ParseError::UnknownName(name) => write!(f, "Unknown name '{name}'"),
+            ParseError::ReturnOutsideMacro => write!(f, "'return' outside of a macro!"),
+            ParseError::ExpectedParamName(found) => write!(f, "Expected a parameter name, found {}", found_str(found)),
+            ParseError::DuplicateParam(name) => write!(f, "Parameter '{name}' is declared twice"),
+            ParseError::BreakOutsideLoop => write!(f, "'break' outside of a loop!"),
+            ParseError::ExpectedPath(found) => write!(f, "Expected a file path after 'include', found {}", found_str(found)),
+            ParseError::MisplacedInclude(construct) => write!(f, "Includes are not allowed in {construct}!"),
+            ParseError::IncludeFailed { path, reason } => write!(f, "Unable to include '{path}': {reason}"),
+            ParseError::IncludeCycle(path) => write!(f, "'{path}' is already being included"),
+            ParseError::StatementInInclude => write!(f, "Included files can't contain top-level statements!"),
+            ParseError::DuplicateMacro { name, .. } => write!(f, "Macro '{name}' is defined more than once"),
+            ParseError::UndefinedMacro(name) => write!(f, "Macro '{name}' is not defined"),
+            ParseError::NamespaceClash { namespace, first } => write!(f, "Namespace '{namespace}' is already used by the include of '{first}'"),
+        }
+    }
+}
+fn found_str(found: &Option<TokenKind>) -> String {
+    match found {
+        Some(t) => t.to_string(),
+        None => "end of input".to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    MacroNotFound(String),
+    DivisionByZero,
+    SpawnFailed(String),
+    ThreadPanicked(String),
+    NoReturnValue(String),
+    ArgumentCount { name: String, expected: usize, found: usize },
+    NotANumber(String),
+    ReadFailed(String),
+    // The operation that overflowed, as written out with its values
+    Overflow(String),
+    // Name of the macro whose call went past the limit
+    RecursionTooDeep(String),
+    // The value that was turned into a character
+    InvalidChar(String),
+    // The value given to `exit`
+    InvalidExitCode(String),
+}
+impl RuntimeError {
+    pub fn code(&self) -> u16 {
+        match self {
+            RuntimeError::MacroNotFound(_) => 301,
+            RuntimeError::DivisionByZero => 302,
+            RuntimeError::SpawnFailed(_) => 306,
+            RuntimeError::ThreadPanicked(_) => 307,
+            RuntimeError::NoReturnValue(_) => 308,
+            RuntimeError::ArgumentCount { .. } => 309,
+            RuntimeError::NotANumber(_) => 310,
+            RuntimeError::ReadFailed(_) => 311,
+            RuntimeError::Overflow(_) => 312,
+            RuntimeError::RecursionTooDeep(_) => 313,
+            RuntimeError::InvalidChar(_) => 314,
+            RuntimeError::InvalidExitCode(_) => 315,
+        }
+    }
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            RuntimeError::MacroNotFound(name) => Some(format!("define it with 'define {name} {{ ... }}'")),
+            RuntimeError::DivisionByZero => Some("check the divisor with an 'if' first".to_string()),
+            RuntimeError::NoReturnValue(_) => Some("end the macro with 'return <value>' to use it as a value".to_string()),
+            RuntimeError::NotANumber(_) => Some("'read' takes numbers separated by whitespace; use 'readchar' to read text".to_string()),
+            RuntimeError::Overflow(_) => Some("integers are 64 bits; run with '--arith big' for unbounded ones, or '--arith wrapping' or '--arith saturating' to keep going".to_string()),
+            RuntimeError::RecursionTooDeep(_) => Some(format!("calls can nest {MAX_CALL_DEPTH} deep; check that the recursion reaches its base case, or turn it into a loop")),
+            RuntimeError::InvalidChar(v) if v == "-1" => Some("'readchar' gives -1 at the end of input; check for it before using 'char'".to_string()),
+            RuntimeError::InvalidChar(_) => Some("characters have code points from 0 to 1114111 (0x10FFFF), except 55296 to 57343 (surrogates)".to_string()),
+            RuntimeError::InvalidExitCode(_) => Some("exit codes go from 0 to 255".to_string()),
+            _ => None,
+        }
+    }
+}
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeError::MacroNotFound(name) => write!(f, "Macro '{name}' not found!"),
+            RuntimeError::DivisionByZero => write!(f, "Attempted division by zero!"),
+            RuntimeError::SpawnFailed(e) => write!(f, "Failed to spawn thread: {e}"),
+            RuntimeError::ThreadPanicked(name) => write!(f, "Thread '{name}' panicked"),
+            RuntimeError::NoReturnValue(name) => write!(f, "Macro '{name}' finished without returning a value!"),
+            RuntimeError::ArgumentCount { name, expected, found } => write!(f, "Macro '{name}' takes {expected} argument(s), but {found} were given!"),
+            RuntimeError::NotANumber(word) => write!(f, "Expected a number on stdin, found '{word}'!"),
+            RuntimeError::ReadFailed(e) => write!(f, "Failed to read stdin: {e}"),
+            RuntimeError::Overflow(expr) => write!(f, "Integer overflow in '{expr}'!"),
+            RuntimeError::RecursionTooDeep(name) => write!(f, "Recursion too deep in macro '{name}'!"),
+            RuntimeError::InvalidChar(v) => write!(f, "{v} is not a valid character code point!"),
+            RuntimeError::InvalidExitCode(v) => write!(f, "{v} is not a valid exit code!"),
+        }
+    }
+}
+
+// Problems worth pointing out that don't stop the program from running
+#[derive(Debug, Clone, PartialEq)]
+pub enum NaquaWarning {
+    UnusedMacro(String, Span),
+}
+impl NaquaWarning {
+    pub fn span(&self) -> Span {
+        match self {
+            NaquaWarning::UnusedMacro(_, s) => *s
+        }
+    }
+    pub fn code(&self) -> u16 {
+        match self {
+            NaquaWarning::UnusedMacro(..) => 1,
+        }
+    }
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            NaquaWarning::UnusedMacro(name, _) => Some(format!("call it with 'run {name}', or remove it")),
+        }
+    }
+}
+impl Display for NaquaWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NaquaWarning::UnusedMacro(name, _) => write!(f, "Macro '{name}' is never used"),
+        }
+    }
+}
+
 impl Parser<'_> {
-    pub fn err(&mut self, msg: &str) -> String {
-        format!("{msg}: Found at expression #{}", self.expr)
+    pub fn err(&self, kind: ParseError) -> NaquaError {
+        NaquaError::Parse(kind, self.span())
+    }
+    // Error for when a value was expected at the current token
+    pub fn expected_value(&self, context: &'static str) -> NaquaError {
+        self.err(ParseError::ExpectedValue { context, found: self.peek().cloned() })
     }
 }
 impl Runner {
-    pub fn err(&mut self, msg: &str) -> String {
-        format!("{msg}: Found at expression #{}", self.expr)
+    pub fn err(&self, kind: RuntimeError) -> NaquaError {
+        NaquaError::Runtime(kind, self.span)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,33 @@
+use std::fmt::{Display, Formatter};
+
+// Location of a token or node in the source; `start`/`end` are byte offsets, `line`/`col` are 1-based.
+// `file` indexes the loaded source files, 0 being the script that was run
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+    pub file: usize,
+}
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, col: usize) -> Self {
+        Self { start, end, line, col, file: 0 }
+    }
+    // Span covering both `self` and `other`, keeping the position of whichever starts first
+    pub fn to(self, other: Span) -> Span {
+        let first = if other.start < self.start { other } else { self };
+        Span {
+            start: first.start,
+            end: self.end.max(other.end),
+            line: first.line,
+            col: first.col,
+            file: first.file,
+        }
+    }
+}
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.col)
+    }
+}
@@ -1,27 +1,40 @@
 use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
 use crate::runtime::eval::EvalType;
-use crate::util::types::Keyword::Print;
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Number {
     Int(i64),
     Float(f64),
+    Char(char),
+    // Only made by `--arith big`, for integers that don't fit in an `Int`
+    Big(Arc<BigInt>),
     Thought
 }
 impl Number {
-    pub fn is_thought(&self) -> bool { if let Number::Thought = self { true } else { false } }
-    pub fn int(&self) -> Option<i64> { if let Number::Int(n) = self { Some(*n) } else { None } }
-    pub fn is_int(&self) -> bool { if let Number::Int(_) = self { true } else { false } }
+    // Integer value; a character counts as its code point
+    pub fn int(&self) -> Option<i64> {
+        match self {
+            Number::Int(n) => Some(*n),
+            Number::Char(c) => Some(*c as i64),
+            _ => None
+        }
+    }
     pub fn float(&self) -> f64 {
-        if let Number::Float(n) = self { *n }
-        else if let Number::Int(n) = self { *n as f64 }
-        else { 0. }
+        match self {
+            Number::Float(n) => *n,
+            Number::Big(n) => n.to_f64().unwrap_or(f64::NAN),
+            _ => self.int().unwrap_or(0) as f64
+        }
     }
-    pub fn is_float(&self) -> bool { if let Number::Float(_) = self { true } else { false } }
     pub fn eval_type(&self) -> Option<EvalType> {
         match self {
             Number::Int(n) => Some(EvalType::Int(*n)), 
             Number::Float(n) => Some(EvalType::Float(*n)), 
+            Number::Char(c) => Some(EvalType::Char(*c)),
+            Number::Big(n) => Some(EvalType::Big(Arc::clone(n))),
             _ => None
         }
     }
@@ -32,35 +45,79 @@ pub enum Operator {
     Sub,
     Mul,
     Div,
+    // `//`, division that keeps integers whole
+    IntDiv,
     Exp,
-    Mod
+    Mod,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or
 }
 impl Operator {
-    pub fn from(c: char) -> Operator {
-        match c {
-            '+' => Operator::Add,
-            '-' => Operator::Sub,
-            '*' => Operator::Mul,
-            '/' => Operator::Div,
-            '^' => Operator::Exp,
-            '%' => Operator::Mod,
-            _ => Operator::Add
+    pub fn from(input: &str) -> Option<Operator> {
+        match input {
+            "+" => Some(Operator::Add),
+            "-" => Some(Operator::Sub),
+            "*" => Some(Operator::Mul),
+            "/" => Some(Operator::Div),
+            "//" => Some(Operator::IntDiv),
+            "^" => Some(Operator::Exp),
+            "%" => Some(Operator::Mod),
+            "==" => Some(Operator::Eq),
+            "!=" => Some(Operator::Ne),
+            "<" => Some(Operator::Lt),
+            "<=" => Some(Operator::Le),
+            ">" => Some(Operator::Gt),
+            ">=" => Some(Operator::Ge),
+            "and" => Some(Operator::And),
+            "or" => Some(Operator::Or),
+            _ => None
+        }
+    }
+    // Binding strength in expressions; higher binds tighter.
+    // `not` sits between `and` and the comparisons.
+    pub fn precedence(self) -> u8 {
+        match self {
+            Operator::Or => 1,
+            Operator::And => 2,
+            Operator::Eq | Operator::Ne | Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge => 4,
+            Operator::Add | Operator::Sub => 5,
+            Operator::Mul | Operator::Div | Operator::IntDiv | Operator::Mod => 6,
+            Operator::Exp => 7
         }
     }
-    pub fn to_char(&self) -> char {
+    // `^` groups to the right (`2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`), everything else to the left
+    pub fn is_right_assoc(self) -> bool {
+        matches!(self, Operator::Exp)
+    }
+    pub fn to_str(self) -> &'static str {
         match self {
-            Operator::Add => '+',
-            Operator::Sub => '-',
-            Operator::Mul => '*',
-            Operator::Div => '/',
-            Operator::Exp => '^',
-            Operator::Mod => '%'
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::IntDiv => "//",
+            Operator::Exp => "^",
+            Operator::Mod => "%",
+            Operator::Eq => "==",
+            Operator::Ne => "!=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::And => "and",
+            Operator::Or => "or"
         }
     }
 }
 impl Display for Operator {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.to_char())
+        write!(f, "{}", self.to_str())
     }
 }
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -76,7 +133,28 @@ pub enum Keyword {
     Break,
     Char,
     Run,
-    Spawn
+    Spawn,
+    Not,
+    Else,
+    While,
+    Repeat,
+    For,
+    From,
+    To,
+    Continue,
+    Return,
+    Include,
+    Arg,
+    Args,
+    Exit,
+    Read,
+    ReadChar,
+    Int,
+    Float,
+    Floor,
+    Ceil,
+    Round,
+    Abs
 }
 impl Keyword {
     pub fn from(input: &str) -> Option<Keyword> {
@@ -93,10 +171,31 @@ impl Keyword {
             "char" => Some(Keyword::Char),
             "run" => Some(Keyword::Run),
             "spawn" => Some(Keyword::Spawn),
+            "not" => Some(Keyword::Not),
+            "else" => Some(Keyword::Else),
+            "while" => Some(Keyword::While),
+            "repeat" => Some(Keyword::Repeat),
+            "for" => Some(Keyword::For),
+            "from" => Some(Keyword::From),
+            "to" => Some(Keyword::To),
+            "continue" => Some(Keyword::Continue),
+            "return" => Some(Keyword::Return),
+            "include" => Some(Keyword::Include),
+            "arg" => Some(Keyword::Arg),
+            "args" => Some(Keyword::Args),
+            "exit" => Some(Keyword::Exit),
+            "read" => Some(Keyword::Read),
+            "readchar" => Some(Keyword::ReadChar),
+            "int" => Some(Keyword::Int),
+            "float" => Some(Keyword::Float),
+            "floor" => Some(Keyword::Floor),
+            "ceil" => Some(Keyword::Ceil),
+            "round" => Some(Keyword::Round),
+            "abs" => Some(Keyword::Abs),
             _ => None
         }
     }
-    pub fn to_str(&self) -> &str {
+    pub fn to_str(self) -> &'static str {
         match self {
             Keyword::Think => "think",
             Keyword::Thought => "thought",
@@ -109,7 +208,63 @@ impl Keyword {
             Keyword::Break => "break",
             Keyword::Char => "char",
             Keyword::Run => "run",
-            Keyword::Spawn => "spawn"
+            Keyword::Spawn => "spawn",
+            Keyword::Not => "not",
+            Keyword::Else => "else",
+            Keyword::While => "while",
+            Keyword::Repeat => "repeat",
+            Keyword::For => "for",
+            Keyword::From => "from",
+            Keyword::To => "to",
+            Keyword::Continue => "continue",
+            Keyword::Return => "return",
+            Keyword::Include => "include",
+            Keyword::Arg => "arg",
+            Keyword::Args => "args",
+            Keyword::Exit => "exit",
+            Keyword::Read => "read",
+            Keyword::ReadChar => "readchar",
+            Keyword::Int => "int",
+            Keyword::Float => "float",
+            Keyword::Floor => "floor",
+            Keyword::Ceil => "ceil",
+            Keyword::Round => "round",
+            Keyword::Abs => "abs"
+        }
+    }
+}
+// Keywords that turn one number into another, like `floor x`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Conversion {
+    // Drops the fraction, rounding toward zero
+    Int,
+    Float,
+    Floor,
+    Ceil,
+    // Halves round away from zero
+    Round,
+    Abs,
+}
+impl Conversion {
+    pub fn from_keyword(keyword: Keyword) -> Option<Conversion> {
+        match keyword {
+            Keyword::Int => Some(Conversion::Int),
+            Keyword::Float => Some(Conversion::Float),
+            Keyword::Floor => Some(Conversion::Floor),
+            Keyword::Ceil => Some(Conversion::Ceil),
+            Keyword::Round => Some(Conversion::Round),
+            Keyword::Abs => Some(Conversion::Abs),
+            _ => None
+        }
+    }
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Conversion::Int => "int",
+            Conversion::Float => "float",
+            Conversion::Floor => "floor",
+            Conversion::Ceil => "ceil",
+            Conversion::Round => "round",
+            Conversion::Abs => "abs",
         }
     }
 }
\ No newline at end of file
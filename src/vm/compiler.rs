@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use crate::parse::{LoopKind, Macro, Node, NodeKind};
+use crate::runtime::eval::EvalType;
+use crate::util::err::RuntimeError;
+use crate::util::span::Span;
+use crate::util::types::{Number, Operator};
+use crate::vm::instr::{Instr, MacroEntry, Program};
+
+// A loop whose body is being compiled
+struct OpenLoop {
+    label: Option<String>,
+    // Values the loop keeps on the stack while it runs
+    state: usize,
+    // Jumps to patch once the loop's end and continue points are known
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+pub struct Compiler<'m> {
+    code: Vec<Instr>,
+    spans: Vec<Span>,
+    macros: &'m HashMap<String, Macro>,
+    ids: HashMap<&'m str, usize>,
+    loops: Vec<OpenLoop>,
+}
+impl<'m> Compiler<'m> {
+    pub fn compile(nodes: &[Node], macros: &'m HashMap<String, Macro>) -> Program {
+        // Sorted so that the same program always compiles to the same code
+        let mut names: Vec<&str> = macros.keys().map(|k| k.as_str()).collect();
+        names.sort();
+        let ids = names.iter().enumerate().map(|(i, name)| (*name, i)).collect();
+
+        let mut compiler = Compiler { code: Vec::new(), spans: Vec::new(), macros, ids, loops: Vec::new() };
+        for node in nodes {
+            compiler.statement(node);
+        }
+        compiler.emit(Instr::Halt, nodes.last().map_or_else(Span::default, |n| n.span));
+
+        let mut entries = Vec::new();
+        for name in names {
+            let entry = compiler.code.len();
+            let body = &macros[name].body;
+            for node in body {
+                compiler.statement(node);
+            }
+            // Falling off the end of a macro is a bare `return`
+            compiler.emit(Instr::Return(false), body.last().map_or(macros[name].span, |n| n.span));
+            entries.push(MacroEntry { name: name.to_string(), entry });
+        }
+
+        Program { code: compiler.code, spans: compiler.spans, macros: entries }
+    }
+
+    fn emit(&mut self, instr: Instr, span: Span) -> usize {
+        self.code.push(instr);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+    // Points the jump at `at` to the next instruction
+    fn patch(&mut self, at: usize) {
+        let target = self.code.len();
+        match &mut self.code[at] {
+            Instr::Jump(t) | Instr::JumpIfFalse(t) | Instr::JumpIfTrue(t) | Instr::Countdown(t) => *t = target,
+            instr => unreachable!("{instr:?} has no jump target")
+        }
+    }
+
+    fn statement(&mut self, node: &Node) {
+        let span = node.span;
+        match &node.kind {
+            NodeKind::Print(v) => match &v.kind {
+                NodeKind::Str(text) => { self.emit(Instr::PrintStr(text.clone()), span); }
+                _ => {
+                    self.expr(v);
+                    self.emit(Instr::Print, span);
+                }
+            },
+            NodeKind::Think(v) => {
+                self.expr(v);
+                self.emit(Instr::Think, span);
+            }
+            NodeKind::Assign(i, v) => {
+                self.expr(i);
+                self.emit(Instr::ToIndex, span);
+                if let NodeKind::Str(text) = &v.kind {
+                    self.emit(Instr::StoreStr(text.clone()), span);
+                    return;
+                }
+                self.expr(v);
+                self.emit(Instr::Store, span);
+            }
+            NodeKind::Run(name, args) => self.call(name, args, false, span),
+            NodeKind::Spawn(name, args) => {
+                if let Some((id, argc)) = self.args(name, args, span) {
+                    self.emit(Instr::Spawn { id, argc }, span);
+                }
+            }
+            NodeKind::If(cond, body, otherwise) => {
+                self.expr(cond);
+                let to_else = self.emit(Instr::JumpIfFalse(0), span);
+                body.iter().for_each(|n| self.statement(n));
+                let to_end = self.emit(Instr::Jump(0), span);
+                self.patch(to_else);
+                otherwise.iter().for_each(|n| self.statement(n));
+                self.patch(to_end);
+            }
+            NodeKind::Loop { label, kind, body } => self.loop_statement(label, kind, body, span),
+            NodeKind::Break(label) => {
+                let target = self.target(label);
+                let pops: usize = self.loops[target..].iter().map(|l| l.state).sum();
+                if pops > 0 { self.emit(Instr::Pop(pops), span); }
+                let jump = self.emit(Instr::Jump(0), span);
+                self.loops[target].breaks.push(jump);
+            }
+            NodeKind::Continue(label) => {
+                let target = self.target(label);
+                let pops: usize = self.loops[target + 1..].iter().map(|l| l.state).sum();
+                if pops > 0 { self.emit(Instr::Pop(pops), span); }
+                let jump = self.emit(Instr::Jump(0), span);
+                self.loops[target].continues.push(jump);
+            }
+            NodeKind::Exit(status) => {
+                self.expr(status);
+                self.emit(Instr::Exit, span);
+            }
+            NodeKind::Return(value) => {
+                if let Some(v) = value {
+                    self.expr(v);
+                }
+                self.emit(Instr::Return(value.is_some()), span);
+            }
+            // Values on their own are rejected by the parser
+            _ => {}
+        }
+    }
+
+    fn loop_statement(&mut self, label: &Option<String>, kind: &LoopKind, body: &[Node], span: Span) {
+        let state = match kind {
+            LoopKind::Forever | LoopKind::While(_) => 0,
+            LoopKind::Repeat(_) => 1,
+            LoopKind::For(..) => 3,
+        };
+        match kind {
+            LoopKind::Repeat(count) => {
+                self.expr(count);
+                self.emit(Instr::ToIndex, span);
+            }
+            LoopKind::For(cell, first, last) => {
+                for n in [cell, first, last] {
+                    self.expr(n);
+                    self.emit(Instr::ToIndex, span);
+                }
+            }
+            _ => {}
+        }
+
+        let top = self.code.len();
+        let exit = match kind {
+            LoopKind::Forever => None,
+            LoopKind::While(cond) => {
+                self.expr(cond);
+                Some(self.emit(Instr::JumpIfFalse(0), span))
+            }
+            LoopKind::Repeat(_) => Some(self.emit(Instr::Countdown(0), span)),
+            LoopKind::For(..) => {
+                self.emit(Instr::ForStore, span);
+                None
+            }
+        };
+
+        self.loops.push(OpenLoop { label: label.clone(), state, breaks: Vec::new(), continues: Vec::new() });
+        body.iter().for_each(|n| self.statement(n));
+        let open = self.loops.pop().unwrap();
+
+        // `continue` in a `for` loop goes to the counter step, everywhere else to the top
+        let next = self.code.len();
+        match kind {
+            LoopKind::For(..) => { self.emit(Instr::ForNext(top), span); }
+            _ => { self.emit(Instr::Jump(top), span); }
+        }
+        for at in open.continues {
+            match &mut self.code[at] {
+                Instr::Jump(t) => *t = if let LoopKind::For(..) = kind { next } else { top },
+                instr => unreachable!("{instr:?} is not a continue jump")
+            }
+        }
+        if let Some(at) = exit {
+            self.patch(at);
+        }
+        for at in open.breaks {
+            self.patch(at);
+        }
+    }
+
+    // Position in `loops` of the loop a `break`/`continue` refers to; the parser makes sure it exists
+    fn target(&self, label: &Option<String>) -> usize {
+        match label {
+            None => self.loops.len() - 1,
+            Some(l) => self.loops.iter().rposition(|open| open.label.as_ref() == Some(l)).unwrap(),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Node], want_value: bool, span: Span) {
+        if let Some((id, argc)) = self.args(name, args, span) {
+            self.emit(Instr::Call { id, argc, want_value }, span);
+        }
+    }
+    // Looks up a macro and compiles its arguments; mistakes become `Fail` so they surface when the call runs
+    fn args(&mut self, name: &str, args: &[Node], span: Span) -> Option<(usize, usize)> {
+        let Some(&id) = self.ids.get(name) else {
+            self.emit(Instr::Fail(RuntimeError::MacroNotFound(name.to_string())), span);
+            return None;
+        };
+        let expected = self.macros[name].params.len();
+        if expected != args.len() {
+            let error = RuntimeError::ArgumentCount { name: name.to_string(), expected, found: args.len() };
+            self.emit(Instr::Fail(error), span);
+            return None;
+        }
+        for arg in args {
+            self.expr(arg);
+        }
+        Some((id, args.len()))
+    }
+
+    fn expr(&mut self, node: &Node) {
+        let span = node.span;
+        match &node.kind {
+            NodeKind::Literal(Number::Int(n)) => { self.emit(Instr::Push(EvalType::Int(*n)), span); }
+            NodeKind::Literal(Number::Float(n)) => { self.emit(Instr::Push(EvalType::Float(*n)), span); }
+            NodeKind::Literal(Number::Char(c)) => { self.emit(Instr::Push(EvalType::Char(*c)), span); }
+            NodeKind::Literal(Number::Thought) => { self.emit(Instr::Thought, span); }
+            NodeKind::Param(i) => { self.emit(Instr::Param(*i), span); }
+            NodeKind::Out(i) => {
+                self.expr(i);
+                self.emit(Instr::Out, span);
+            }
+            NodeKind::Arg(i) => {
+                self.expr(i);
+                self.emit(Instr::Arg, span);
+            }
+            NodeKind::ArgCount => { self.emit(Instr::ArgCount, span); }
+            NodeKind::Read => { self.emit(Instr::Read, span); }
+            NodeKind::ReadChar => { self.emit(Instr::ReadChar, span); }
+            NodeKind::Char(v) => {
+                self.expr(v);
+                self.emit(Instr::Char, span);
+            }
+            NodeKind::Negate(v) => {
+                self.expr(v);
+                self.emit(Instr::Negate, span);
+            }
+            NodeKind::Convert(c, v) => {
+                self.expr(v);
+                self.emit(Instr::Convert(*c), span);
+            }
+            NodeKind::Not(v) => {
+                self.expr(v);
+                self.emit(Instr::Not, span);
+            }
+            NodeKind::Eval(l, op @ (Operator::And | Operator::Or), r) => {
+                // The right side only runs when the left side doesn't decide the result
+                self.expr(l);
+                let short = match op {
+                    Operator::And => self.emit(Instr::JumpIfFalse(0), span),
+                    _ => self.emit(Instr::JumpIfTrue(0), span),
+                };
+                self.expr(r);
+                self.emit(Instr::Truthy, span);
+                let to_end = self.emit(Instr::Jump(0), span);
+                self.patch(short);
+                self.emit(Instr::Push(EvalType::from_bool(*op == Operator::Or)), span);
+                self.patch(to_end);
+            }
+            NodeKind::Eval(l, op, r) => {
+                self.expr(l);
+                self.expr(r);
+                self.emit(Instr::Binary(*op), span);
+            }
+            NodeKind::Run(name, args) => self.call(name, args, true, span),
+            // Statements never appear where a value is expected
+            _ => { self.emit(Instr::Push(EvalType::Int(0)), span); }
+        }
+    }
+}
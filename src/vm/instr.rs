@@ -0,0 +1,73 @@
+use crate::runtime::eval::EvalType;
+use crate::util::err::RuntimeError;
+use crate::util::span::Span;
+use crate::util::types::{Conversion, Operator};
+
+// Jump targets are absolute positions in `Program::code`.
+// Loops keep their counters on the value stack: one value for `repeat`, three (cell, counter, last) for `for`
+#[derive(Debug, Clone)]
+pub enum Instr {
+    Push(EvalType),
+    Thought,
+    // Parameter of the innermost call frame
+    Param(usize),
+    // index -> value stored at that index
+    Out,
+    // index -> script argument at that index
+    Arg,
+    ArgCount,
+    // -> next number / next character code point from stdin
+    Read,
+    ReadChar,
+    Char,
+    Negate,
+    Convert(Conversion),
+    Not,
+    // value -> 1 or 0
+    Truthy,
+    Binary(Operator),
+    ToIndex,
+    Pop(usize),
+
+    Print,
+    PrintStr(String),
+    Think,
+    // index, value ->
+    Store,
+    // index -> stores the characters of the text from there, followed by a 0
+    StoreStr(String),
+
+    Jump(usize),
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+    // Counts down the `repeat` counter, or drops it and jumps once it runs out
+    Countdown(usize),
+    // Writes the `for` counter into its cell
+    ForStore,
+    // Steps the `for` counter and jumps back, or drops the loop state once the last value is done
+    ForNext(usize),
+
+    Call { id: usize, argc: usize, want_value: bool },
+    Spawn { id: usize, argc: usize },
+    // Whether a value is on the stack to hand back
+    Return(bool),
+    // status -> ends the process
+    Exit,
+    // Errors the tree walker only finds once the code runs
+    Fail(RuntimeError),
+    Halt,
+}
+
+#[derive(Debug, Clone)]
+pub struct MacroEntry {
+    pub name: String,
+    pub entry: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub code: Vec<Instr>,
+    // Source location of each instruction, for runtime errors
+    pub spans: Vec<Span>,
+    pub macros: Vec<MacroEntry>,
+}
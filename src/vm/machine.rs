@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use crate::runtime::eval::EvalType;
+use crate::runtime::input::{read_char, read_number};
+use crate::runtime::op_eval::{arith, convert, negate, Arith};
+use crate::runtime::runtime::{exit, print, store_text, wait_for_threads, ThreadHandles, MAX_CALL_DEPTH, SPAWN_STACK_SIZE};
+use crate::util::err::{NaquaError, NaquaResult, RuntimeError};
+use crate::util::types::Number;
+use crate::vm::instr::{Instr, Program};
+
+struct Frame {
+    // Where to continue once the macro returns; `None` ends the thread
+    ret: Option<usize>,
+    // Position of the first argument on the value stack
+    base: usize,
+    want_value: bool,
+    // Position of the call instruction, for errors
+    call: usize,
+    id: usize,
+}
+
+pub struct Vm {
+    program: Arc<Program>,
+    values: Vec<EvalType>,
+    frames: Vec<Frame>,
+    pub stack: HashMap<i64, Number>,
+    pub thought: Number,
+    // Arguments given to the script on the command line
+    pub args: Arc<Vec<Number>>,
+    pub arith: Arith,
+    // Position of the instruction being executed, used to locate runtime errors
+    pc: usize,
+    thread_handles: ThreadHandles,
+}
+impl Vm {
+    pub fn new(program: Program) -> Self {
+        Self::with_program(Arc::new(program))
+    }
+    fn with_program(program: Arc<Program>) -> Self {
+        Self {
+            program,
+            values: Vec::new(),
+            frames: Vec::new(),
+            stack: HashMap::new(),
+            thought: Number::Int(0),
+            args: Arc::new(Vec::new()),
+            arith: Arith::default(),
+            pc: 0,
+            thread_handles: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+    pub fn run(&mut self) -> NaquaResult<()> {
+        self.exec(0)?;
+        wait_for_threads(&self.thread_handles, |e| self.err(e))
+    }
+
+    fn exec(&mut self, start: usize) -> NaquaResult<()> {
+        let program = Arc::clone(&self.program);
+        let mut pc = start;
+        loop {
+            self.pc = pc;
+            pc += 1;
+            match &program.code[self.pc] {
+                Instr::Push(v) => self.values.push(v.clone()),
+                Instr::Thought => self.values.push(self.thought.eval_type().unwrap()),
+                Instr::Param(i) => {
+                    let base = self.frames.last().map_or(0, |f| f.base);
+                    self.values.push(self.values[base + i].clone());
+                }
+                Instr::Out => {
+                    let index = self.pop().to_index();
+                    self.values.push(self.stack.get(&index).unwrap_or(&Number::Int(0)).eval_type().unwrap());
+                }
+                Instr::Arg => {
+                    let index = self.pop().to_index();
+                    let arg = usize::try_from(index).ok().and_then(|i| self.args.get(i));
+                    self.values.push(arg.unwrap_or(&Number::Int(0)).eval_type().unwrap());
+                }
+                Instr::ArgCount => self.values.push(EvalType::Int(self.args.len() as i64)),
+                Instr::Read => {
+                    let v = read_number().map_err(|e| self.err(e))?;
+                    self.values.push(v);
+                }
+                Instr::ReadChar => {
+                    let v = read_char().map_err(|e| self.err(e))?;
+                    self.values.push(v);
+                }
+                Instr::Char => {
+                    let v = self.pop();
+                    let c = v.to_char().map_err(|e| self.err(e))?;
+                    self.values.push(c);
+                }
+                Instr::Negate => {
+                    let v = self.pop();
+                    let v = negate(v, self.arith).map_err(|e| self.err(e))?;
+                    self.values.push(v);
+                }
+                Instr::Convert(c) => {
+                    let v = self.pop();
+                    let v = convert(v, *c, self.arith).map_err(|e| self.err(e))?;
+                    self.values.push(v);
+                }
+                Instr::Not => {
+                    let v = self.pop();
+                    self.values.push(EvalType::from_bool(!v.is_truthy()));
+                }
+                Instr::Truthy => {
+                    let v = self.pop();
+                    self.values.push(EvalType::from_bool(v.is_truthy()));
+                }
+                Instr::Binary(op) => {
+                    let r = self.pop();
+                    let l = self.pop();
+                    let v = arith(l.to_num(), *op, r.to_num(), self.arith).map_err(|e| self.err(e))?;
+                    self.values.push(v);
+                }
+                Instr::ToIndex => {
+                    let v = self.pop();
+                    self.values.push(EvalType::Int(v.to_index()));
+                }
+                Instr::Pop(n) => {
+                    self.values.truncate(self.values.len() - n);
+                }
+
+                Instr::Print => print(self.pop()),
+                Instr::PrintStr(text) => print(text),
+                Instr::Think => {
+                    let v = self.pop();
+                    self.thought = v.to_num();
+                }
+                Instr::Store => {
+                    let value = self.pop();
+                    let index = self.pop().to_index();
+                    self.stack.insert(index, value.to_num());
+                }
+
+                Instr::StoreStr(text) => {
+                    let index = self.pop().to_index();
+                    store_text(&mut self.stack, index, text);
+                }
+
+                Instr::Jump(t) => pc = *t,
+                Instr::JumpIfFalse(t) => {
+                    if !self.pop().is_truthy() { pc = *t; }
+                }
+                Instr::JumpIfTrue(t) => {
+                    if self.pop().is_truthy() { pc = *t; }
+                }
+                Instr::Countdown(t) => {
+                    let left = self.values.last().unwrap().to_index();
+                    if left <= 0 {
+                        self.values.pop();
+                        pc = *t;
+                    } else {
+                        *self.values.last_mut().unwrap() = EvalType::Int(left - 1);
+                    }
+                }
+                Instr::ForStore => {
+                    let n = self.values.len();
+                    let (cell, i) = (self.values[n - 3].to_index(), self.values[n - 2].to_index());
+                    self.stack.insert(cell, Number::Int(i));
+                }
+                Instr::ForNext(t) => {
+                    let n = self.values.len();
+                    let (i, last) = (self.values[n - 2].to_index(), self.values[n - 1].to_index());
+                    if i == last {
+                        self.values.truncate(n - 3);
+                    } else {
+                        // The counter always moves towards `last`
+                        self.values[n - 2] = EvalType::Int(i + (last - i).signum());
+                        pc = *t;
+                    }
+                }
+
+                Instr::Call { id, argc, want_value } => {
+                    if self.frames.len() >= MAX_CALL_DEPTH {
+                        return Err(self.err(RuntimeError::RecursionTooDeep(program.macros[*id].name.clone())));
+                    }
+                    let base = self.values.len() - argc;
+                    self.frames.push(Frame { ret: Some(pc), base, want_value: *want_value, call: self.pc, id: *id });
+                    pc = program.macros[*id].entry;
+                }
+                Instr::Spawn { id, argc } => {
+                    let args = self.values.split_off(self.values.len() - argc);
+                    let name = program.macros[*id].name.clone();
+                    let entry = program.macros[*id].entry;
+                    let mut vm = Vm::with_program(Arc::clone(&program));
+                    vm.values = args;
+                    vm.args = Arc::clone(&self.args);
+                    vm.arith = self.arith;
+                    // Threads spawned from the new one are waited for along with it
+                    vm.thread_handles = Arc::clone(&self.thread_handles);
+                    vm.frames.push(Frame { ret: None, base: 0, want_value: false, call: self.pc, id: *id });
+
+                    let handle = thread::Builder::new()
+                        .name(name)
+                        .stack_size(SPAWN_STACK_SIZE)
+                        .spawn(move || vm.exec(entry))
+                        .map_err(|e| self.err(RuntimeError::SpawnFailed(e.to_string())))?;
+                    self.thread_handles.lock().unwrap().push(handle);
+                }
+                Instr::Return(has_value) => {
+                    let value = if *has_value { Some(self.pop()) } else { None };
+                    let frame = self.frames.pop().unwrap();
+                    self.values.truncate(frame.base);
+                    if frame.want_value {
+                        match value {
+                            Some(v) => self.values.push(v),
+                            None => {
+                                self.pc = frame.call;
+                                return Err(self.err(RuntimeError::NoReturnValue(program.macros[frame.id].name.clone())));
+                            }
+                        }
+                    }
+                    match frame.ret {
+                        Some(r) => pc = r,
+                        None => return Ok(())
+                    }
+                }
+                Instr::Exit => {
+                    let status = self.pop().to_exit_code().map_err(|e| self.err(e))?;
+                    exit(status)
+                }
+                Instr::Fail(e) => return Err(self.err(e.clone())),
+                Instr::Halt => return Ok(()),
+            }
+        }
+    }
+
+    fn pop(&mut self) -> EvalType {
+        self.values.pop().expect("compiled code keeps the value stack balanced")
+    }
+    fn err(&self, kind: RuntimeError) -> NaquaError {
+        NaquaError::Runtime(kind, self.program.spans[self.pc])
+    }
+}
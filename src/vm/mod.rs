@@ -0,0 +1,4 @@
+// Bytecode backend: the AST is compiled into a flat instruction stream and run by a stack machine
+pub mod instr;
+pub mod compiler;
+pub mod machine;
@@ -0,0 +1,35 @@
+mod common;
+
+use common::run_file;
+
+// Runs a script from tests/scripts/backends on the tree walker and on the VM, which must print the same
+// output and exit with the same code, and returns the status they agreed on
+fn same_on_both(script: &str, flags: &[&str]) -> i32 {
+    let path = format!("tests/scripts/backends/{script}.naq");
+    let walker = run_file(&path, flags);
+    let vm = run_file(&path, &[flags, &["--vm"]].concat());
+    assert_eq!(walker.stdout, vm.stdout, "{script} {flags:?} prints differently on the VM");
+    assert_eq!(walker.status, vm.status, "{script} {flags:?} exits differently on the VM: {}", vm.stderr);
+    walker.status
+}
+
+#[test]
+fn scripts_behave_the_same_on_both_backends() {
+    for script in ["loops", "labels", "returns", "spawn", "strings", "chars"] {
+        assert_eq!(same_on_both(script, &[]), 0, "{script} failed");
+    }
+}
+
+#[test]
+fn arithmetic_modes_behave_the_same_on_both_backends() {
+    assert_eq!(same_on_both("arith", &[]), 1);
+    for mode in ["wrapping", "saturating", "big"] {
+        assert_eq!(same_on_both("arith", &["--arith", mode]), 0, "--arith {mode} failed");
+    }
+}
+
+#[test]
+fn errors_and_exits_behave_the_same_on_both_backends() {
+    assert_eq!(same_on_both("errors", &[]), 1);
+    assert_eq!(same_on_both("exit", &[]), 3);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use std::fs;
+use common::{run, run_args, run_with_input};
+
+#[test]
+fn check_does_not_run_the_script() {
+    let result = run_args(&["check", "-e", "print 1"]);
+    assert_eq!(result.status, 0);
+    assert_eq!(result.stdout, "No errors found in <-e>");
+    assert_eq!(run_args(&["check", "-e", "print ("]).status, 4);
+}
+
+#[test]
+fn tokens_lists_each_token_with_its_position() {
+    let result = run_args(&["tokens", "-e", "think 1"]);
+    assert_eq!(result.status, 0);
+    assert_eq!(result.stdout, "1:1      keyword 'think'\n1:7      number '1'");
+}
+
+#[test]
+fn ast_shows_the_tree_that_runs() {
+    let result = run_args(&["ast", "-e", "print 1 + out 0"]);
+    assert_eq!(result.status, 0);
+    let lines: Vec<&str> = result.stdout.lines().map(str::trim_end).collect();
+    assert_eq!(lines, ["print                    1:1", "  +                        1:7", "    1                        1:7", "    out                      1:11", "      0                        1:15"]);
+}
+
+#[test]
+fn fmt_prints_the_script_reindented() {
+    let result = run_args(&["fmt", "-e", "if 1 {\nprint 1\n}"]);
+    assert_eq!(result.status, 0);
+    assert_eq!(result.stdout, "if 1 {\n    print 1\n}");
+}
+
+#[test]
+fn fmt_write_saves_the_file() {
+    let path = std::env::temp_dir().join(format!("naqua-fmt-{}.naq", std::process::id()));
+    fs::write(&path, "repeat 2 {\nprint 1\n}\n").unwrap();
+    let result = run_args(&["fmt", path.to_str().unwrap(), "--write"]);
+    let written = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(result.status, 0);
+    assert_eq!(written, "repeat 2 {\n    print 1\n}\n");
+}
+
+#[test]
+fn script_from_stdin() {
+    for flags in [&["-"][..], &["-", "--vm"]] {
+        assert_eq!(run_with_input(flags, "print 2 + 3").stdout, "5");
+    }
+}
+
+#[test]
+fn script_arguments_after_double_dash() {
+    for flags in [&["--", "4", "5"][..], &["--vm", "--", "4", "5"]] {
+        assert_eq!(run("print arg 0 + arg 1\nprint \" \"\nprint args", flags).stdout, "9 2");
+    }
+}
+
+#[test]
+fn command_line_mistakes_exit_with_2() {
+    for args in [&["--bogus"][..], &["a.naq", "b.naq"], &["-e"], &["-e", "print 1", "--arith", "huge"]] {
+        let result = run_args(args);
+        assert_eq!(result.status, 2, "{args:?}");
+        assert!(result.stderr.starts_with("Error: "), "{args:?}: {}", result.stderr);
+    }
+}
+
+#[test]
+fn diagnostics_are_plain_when_stderr_is_not_a_terminal() {
+    let result = run("print (", &[]);
+    assert!(result.stderr.contains("parsing error[E"), "{}", result.stderr);
+    assert!(!result.stderr.contains('\u{1b}'), "{:?}", result.stderr);
+}
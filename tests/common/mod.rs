@@ -0,0 +1,57 @@
+// Shared by every test file, and each one uses only part of it
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+// What a script printed, without the blank lines `run` puts around its output, and the status it exited with
+pub struct Run {
+    pub stdout: String,
+    pub stderr: String,
+    pub status: i32,
+}
+
+// Runs `code` as an inline script with the given extra flags
+pub fn run(code: &str, flags: &[&str]) -> Run {
+    run_args(&[&["-e", code], flags].concat())
+}
+
+// Runs the script file at `path` with the given extra flags
+pub fn run_file(path: &str, flags: &[&str]) -> Run {
+    run_args(&[&[path], flags].concat())
+}
+
+// Runs naqua with exactly the given command-line arguments
+pub fn run_args(args: &[&str]) -> Run {
+    let output = Command::new(env!("CARGO_BIN_EXE_naqua"))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to start naqua");
+    from_output(output)
+}
+
+// Runs naqua with the given command-line arguments, typing `input` on its stdin
+pub fn run_with_input(args: &[&str], input: &str) -> Run {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_naqua"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start naqua");
+    // Dropping stdin closes it, so the script sees the end of its input
+    child.stdin.take().expect("stdin is piped").write_all(input.as_bytes()).expect("failed to write stdin");
+    from_output(child.wait_with_output().expect("failed to wait for naqua"))
+}
+
+fn from_output(output: Output) -> Run {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = stdout.strip_prefix('\n').unwrap_or(&stdout);
+    let stdout = stdout.strip_suffix('\n').unwrap_or(stdout);
+    Run {
+        stdout: stdout.to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        status: output.status.code().unwrap_or(-1),
+    }
+}
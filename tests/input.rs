@@ -0,0 +1,41 @@
+mod common;
+
+use common::run_with_input;
+
+const BACKENDS: [&[&str]; 2] = [&[], &["--vm"]];
+
+fn run(code: &str, flags: &[&str], input: &str) -> common::Run {
+    run_with_input(&[&["-e", code], flags].concat(), input)
+}
+
+#[test]
+fn read_numbers() {
+    for flags in BACKENDS {
+        assert_eq!(run("print read + 1\nprint \" \"\nprint read", flags, "12\n  2.5").stdout, "13 2.5");
+    }
+}
+
+#[test]
+fn readchar_reads_code_points() {
+    for flags in BACKENDS {
+        // The newline after the number is left for `readchar`
+        assert_eq!(run("print read\nprint readchar\nprint readchar char", flags, "7\né").stdout, "710é");
+    }
+}
+
+#[test]
+fn end_of_input_reads_as_minus_one() {
+    for flags in BACKENDS {
+        assert_eq!(run("print read\nprint readchar", flags, "").stdout, "-1-1");
+        assert_eq!(run("print read\nprint read", flags, "3 ").stdout, "3-1");
+    }
+}
+
+#[test]
+fn read_rejects_words() {
+    for flags in BACKENDS {
+        let result = run("print read", flags, "x");
+        assert_eq!(result.status, 1);
+        assert!(result.stderr.contains("E310"), "{}", result.stderr);
+    }
+}
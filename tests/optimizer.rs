@@ -0,0 +1,35 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use common::run_file;
+
+// Every script under `dir`, including those in subdirectories
+fn scripts(dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(dir).expect("failed to read the scripts directory") {
+        let path = entry.expect("failed to read a scripts directory entry").path();
+        if path.is_dir() {
+            found.extend(scripts(&path));
+        } else if path.extension().is_some_and(|e| e == "naq") {
+            found.push(path);
+        }
+    }
+    found
+}
+
+// The optimizer must not change what a script does: output and exit code are the same with `--no-opt`
+#[test]
+fn scripts_behave_the_same_without_the_optimizer() {
+    let scripts = scripts(Path::new("tests/scripts"));
+    assert!(!scripts.is_empty());
+    for path in scripts {
+        let path = path.to_str().expect("script paths are UTF-8");
+        for backend in [&[][..], &["--vm"]] {
+            let optimized = run_file(path, backend);
+            let plain = run_file(path, &[backend, &["--no-opt"]].concat());
+            assert_eq!(optimized.stdout, plain.stdout, "{path} {backend:?} prints differently with --no-opt");
+            assert_eq!(optimized.status, plain.status, "{path} {backend:?} exits differently with --no-opt: {}", plain.stderr);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use common::run_with_input;
+
+// Output of a REPL session fed `input`, without the banner
+fn session(input: &str) -> String {
+    let result = run_with_input(&["repl"], input);
+    assert_eq!(result.status, 0, "{}", result.stderr);
+    result.stdout.strip_prefix("Naqua REPL, :help lists the commands\n").expect("the banner comes first").to_string()
+}
+
+#[test]
+fn state_carries_over_between_entries() {
+    let out = session("define inc(n) { return n + 1 }\nthink run inc(4)\nprint thought\n:macros\n");
+    // `think run inc(4)` prints nothing, so it adds no blank line
+    assert_eq!(out, "> > > 5\n> inc(n)\n> ");
+}
+
+#[test]
+fn reset_forgets_everything() {
+    let result = run_with_input(&["repl"], "define inc(n) { return n + 1 }\nthink run inc(4)\n:reset\n:thought\n:macros\nrun inc(1)\n");
+    assert!(result.stdout.ends_with("> 0\n> (none)\n> > "), "{}", result.stdout);
+    assert!(result.stderr.contains("E226"), "{}", result.stderr);
+}
+
+#[test]
+fn entries_continue_until_braces_close() {
+    assert_eq!(session("repeat 2 {\nprint 1\n}\n"), "> ... ... 11\n> ");
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::run;
+
+const COUNT_DOWN: &str = "define f(n) {\n    if n == 0 { return 0 }\n    return run f(n - 1) + 1\n}\n";
+
+#[test]
+fn deep_recursion_is_an_error() {
+    for flags in [&[][..], &["--vm"]] {
+        let ok = run(&format!("{COUNT_DOWN}print run f(3999)"), flags);
+        assert_eq!((ok.stdout.as_str(), ok.status), ("3999", 0));
+
+        let deep = run(&format!("{COUNT_DOWN}print run f(100000)"), flags);
+        assert_eq!(deep.status, 1);
+        assert!(deep.stderr.contains("E313"), "{}", deep.stderr);
+    }
+}
+
+#[test]
+fn exit_codes_by_stage() {
+    let bad_token = run("print 1 $ 2", &[]);
+    assert_eq!(bad_token.status, 3);
+    assert!(bad_token.stderr.contains("E103"), "{}", bad_token.stderr);
+
+    let bad_parse = run("print (1 + 2", &[]);
+    assert_eq!(bad_parse.status, 4);
+    assert!(bad_parse.stderr.contains("parsing error"), "{}", bad_parse.stderr);
+
+    for flags in [&[][..], &["--vm"]] {
+        assert_eq!(run("print 1 // 0", flags).status, 1);
+    }
+}
+
+#[test]
+fn exit_takes_codes_from_0_to_255() {
+    for flags in [&[][..], &["--vm"]] {
+        assert_eq!(run("exit", flags).status, 0);
+        assert_eq!(run("exit 255", flags).status, 255);
+        assert_eq!(run("print 1\nexit 7\nprint 2", flags).stdout, "1");
+        for code in ["256", "-1", "4294967296"] {
+            let result = run(&format!("exit {code}"), flags);
+            assert_eq!(result.status, 1, "exit {code}");
+            assert!(result.stderr.contains("E315"), "{}", result.stderr);
+        }
+    }
+}
+
+#[test]
+fn deep_recursion_in_spawned_threads_is_an_error() {
+    for flags in [&[][..], &["--vm"]] {
+        let deep = run(&format!("{COUNT_DOWN}define g {{ print run f(100000) }}\nspawn g"), flags);
+        assert_eq!(deep.status, 1);
+        assert!(deep.stderr.contains("E313"), "{}", deep.stderr);
+    }
+}
+
+#[test]
+fn many_spawned_threads() {
+    for flags in [&[][..], &["--vm"]] {
+        let result = run("define w(n) { 0 in n }\nrepeat 300 { spawn w(1) }\nprint 1", flags);
+        assert_eq!((result.stdout.as_str(), result.status), ("1", 0), "{}", result.stderr);
+    }
+}
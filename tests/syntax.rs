@@ -0,0 +1,118 @@
+mod common;
+
+use common::{run, run_file};
+
+#[test]
+fn subtraction_without_spaces_after_thought() {
+    assert_eq!(run("think 5\nprint thought-1", &[]).stdout, "4");
+    assert_eq!(run("think 5\nprint thought+1", &[]).stdout, "6");
+}
+
+#[test]
+fn subtraction_without_spaces_after_parameter() {
+    assert_eq!(run("define f(n) { return n-1 }\nprint run f(3)", &[]).stdout, "2");
+}
+
+#[test]
+fn hyphenated_names() {
+    assert_eq!(run("define my-macro { print 7 }\nrun my-macro", &[]).stdout, "7");
+}
+
+#[test]
+fn char_inside_expressions() {
+    for flags in [&[][..], &["--vm"]] {
+        assert_eq!(run("print (65 char)", flags).stdout, "A");
+        assert_eq!(run("print 65 char + 1", flags).stdout, "66");
+        assert_eq!(run("print int (66 char)", flags).stdout, "66");
+        assert_eq!(run("print 10 + 55 char", flags).stdout, "A");
+    }
+}
+
+#[test]
+fn invalid_code_points_are_errors() {
+    for flags in [&[][..], &["--vm"]] {
+        assert_eq!(run("print 1114111 char", flags).stdout, "\u{10FFFF}");
+        for code in ["-1", "55296", "1114112"] {
+            let result = run(&format!("print {code} char"), flags);
+            assert_eq!(result.status, 1);
+            assert!(result.stderr.contains("E314"), "{}", result.stderr);
+        }
+    }
+}
+
+#[test]
+fn includes_with_the_same_name() {
+    let result = run_file("tests/scripts/include/clash.naq", &[]);
+    assert_eq!(result.status, 4);
+    assert!(result.stderr.contains("E227"), "{}", result.stderr);
+    assert_eq!(run_file("tests/scripts/include/twice.naq", &[]).stdout, "1");
+}
+
+#[test]
+fn duplicate_macros_name_the_first_definition() {
+    let result = run_file("tests/scripts/include/duplicate.naq", &[]);
+    assert_eq!(result.status, 4);
+    assert!(result.stderr.contains("first defined at tests/scripts/include/duplicate.naq:2:8"), "{}", result.stderr);
+}
+
+#[test]
+fn end_of_input_errors_point_past_the_last_token() {
+    assert!(run("think", &[]).stderr.contains("<-e>:1:6"));
+    assert!(run("print 1 +", &[]).stderr.contains("<-e>:1:10"));
+}
+
+#[test]
+fn smallest_integer_literal() {
+    for flags in [&[][..], &["--vm"]] {
+        assert_eq!(run("print -9223372036854775808", flags).stdout, "-9223372036854775808");
+        assert_eq!(run("print -9223372036854775808 + 1", flags).stdout, "-9223372036854775807");
+        for code in ["print 9223372036854775808", "print -(9223372036854775808)", "print -9223372036854775808 ^ 2"] {
+            assert!(run(code, flags).stderr.contains("E107"), "{code}");
+        }
+    }
+}
+
+#[test]
+fn if_shorthand_only_for_literal_numbers() {
+    for flags in [&[][..], &["--vm"]] {
+        assert_eq!(run("think 3\nif 3 { print 1 }\nif 4 { print 2 }", flags).stdout, "1");
+        assert_eq!(run("think -1\nif -1 { print 1 }", flags).stdout, "1");
+        assert_eq!(run("if thought { print 1 } else { print 2 }", flags).stdout, "2");
+        assert_eq!(run("0 in 5\nif out 0 { print 1 } else { print 2 }", flags).stdout, "1");
+    }
+}
+
+#[test]
+fn integer_literals() {
+    for (code, value) in [("0xff", "255"), ("0XfF", "255"), ("0b1010", "10"), ("0o17", "15"), ("1_000_000", "1000000"),
+                          ("0x_ff", "255"), ("0x7fff_ffff_ffff_ffff", "9223372036854775807")] {
+        assert_eq!(run(&format!("print {code}"), &[]).stdout, value, "{code}");
+    }
+}
+
+#[test]
+fn float_literals() {
+    for (code, value) in [("1e3", "1000"), ("2.5e-3", "0.0025"), ("1.5E+2", "150"), (".5", "0.5"), ("1_000.25", "1000.25")] {
+        assert_eq!(run(&format!("print {code}"), &[]).stdout, value, "{code}");
+    }
+}
+
+#[test]
+fn malformed_and_out_of_range_literals() {
+    for (code, error) in [("0xfg", "E101"), ("0b102", "E101"), ("0o8", "E101"), ("0x", "E101"),
+                          ("99999999999999999999", "E107"), ("0x1_0000_0000_0000_0000", "E107"), ("1e999", "E107")] {
+        let result = run(&format!("print {code}"), &[]);
+        assert_eq!(result.status, 3, "{code}");
+        assert!(result.stderr.contains(error), "{code}: {}", result.stderr);
+    }
+}
+
+#[test]
+fn precedence_and_associativity() {
+    for (code, value) in [("2*3+4", "10"), ("2+3*4", "14"), ("2-3-4", "-5"), ("8/2/2", "2"), ("2^3^2", "512"),
+                          ("-2^2", "-4"), ("5 -3", "2"), ("7 - -3", "10"), ("2*-3", "-6"), ("(2+3)*4", "20")] {
+        for flags in [&[][..], &["--vm"], &["--no-opt"]] {
+            assert_eq!(run(&format!("print {code}"), flags).stdout, value, "{code} {flags:?}");
+        }
+    }
+}